@@ -0,0 +1,154 @@
+use super::{dry_run, dry_run_print, garlic_print, CmdError, Project};
+use colored::Colorize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Directories that never count as inputs, they are build output or dependencies
+const IGNORED_DIRS: [&str; 4] = [".git", "node_modules", "pkg", "target"];
+
+/// Skips a generation step when its inputs are unchanged since it last succeeded.
+///
+/// The fingerprint of the inputs is kept in `.garlic/cache/<name>`. It is only kept when the
+/// inputs didn't change while the step ran, e.g. because the step producing them still ran.
+#[must_use]
+pub struct Cache<'a> {
+    name: &'static str,
+    project: &'a Project,
+    inputs: Vec<PathBuf>,
+    outputs: Vec<PathBuf>,
+}
+
+impl<'a> Cache<'a> {
+    pub fn new(project: &'a Project, name: &'static str) -> Self {
+        Cache {
+            name,
+            project,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// A file or directory, relative to the project root, that the step reads
+    pub fn input(mut self, path: impl AsRef<Path>) -> Self {
+        self.inputs.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// A file or directory the step generates, the step runs again when it's missing
+    pub fn output(mut self, path: impl AsRef<Path>) -> Self {
+        self.outputs.push(path.as_ref().to_owned());
+        self
+    }
+
+    /// Calls `run` unless the inputs are unchanged, or always with `force`
+    pub fn run(
+        self,
+        force: bool,
+        run: impl FnOnce() -> Result<(), CmdError>,
+    ) -> Result<(), CmdError> {
+        let root = &self.project.root;
+        let fingerprint = fingerprint(root, &self.inputs);
+        let file = self.project.dotgarlic("cache").join(self.name);
+
+        let outputs_exist = self.outputs.iter().all(|output| root.join(output).exists());
+        let cached = fs::read_to_string(&file).is_ok_and(|cached| cached.trim() == fingerprint);
+
+        if !force && cached && outputs_exist {
+            garlic_print(format!(
+                "Skipping {}, its inputs haven't changed. Use --force to run it anyway",
+                self.name.cyan()
+            ));
+            return Ok(());
+        }
+
+        run()?;
+
+        if dry_run() {
+            dry_run_print(format!("Would update {}", file.display()));
+            return Ok(());
+        }
+
+        if self::fingerprint(root, &self.inputs) != fingerprint {
+            return Ok(());
+        }
+
+        // A missing fingerprint only means the step runs again next time
+        let _ = fs::create_dir_all(file.parent().expect("Expected a cache directory"))
+            .and_then(|()| fs::write(&file, fingerprint));
+        Ok(())
+    }
+}
+
+/// A hex SHA-256 over the paths and contents of every file in `inputs`
+pub fn fingerprint(root: &Path, inputs: &[PathBuf]) -> String {
+    let mut files = Vec::new();
+    for input in inputs {
+        collect_files(&root.join(input), &mut files);
+    }
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for file in files {
+        let relative = file.strip_prefix(root).unwrap_or(&file);
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0]);
+
+        let mut contents = fs::read(&file).unwrap_or_default();
+        if file.file_name().is_some_and(|name| name == "Cargo.lock") {
+            contents = without_local_versions(&String::from_utf8_lossy(&contents)).into_bytes();
+        }
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(contents);
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// `Cargo.lock` without the versions of the workspace's own packages, which lack a `source`.
+/// `cargo set-version` bumps those without changing what any of them is built from
+fn without_local_versions(lock: &str) -> String {
+    lock.split("\n\n")
+        .map(|entry| {
+            let local = entry.starts_with("[[package]]")
+                && !entry.lines().any(|line| line.starts_with("source = "));
+            match local {
+                true => entry
+                    .lines()
+                    .filter(|line| !line.starts_with("version = "))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                false => entry.to_owned(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_file() {
+        files.push(path.to_owned());
+        return;
+    }
+
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let ignored = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| IGNORED_DIRS.contains(&name));
+
+        if !ignored {
+            collect_files(&entry.path(), files);
+        }
+    }
+}
@@ -1,44 +1,198 @@
-use super::{error, error_opt, find_dotgarlic_directory};
+use super::{
+    buffering_output, dry_run, dry_run_print, error, error_opt, garlic_exit, print_line,
+    print_output,
+    process::{self, Stopped},
+    run_log::log_run,
+    shell_words::{join, quote, split},
+};
 use colored::Colorize;
 use std::{
-    env::{self, current_dir},
+    env,
     ffi::OsStr,
     fmt::Display,
-    path::PathBuf,
-    process::{exit, Command as StdCommand},
+    io::{self, IsTerminal, Read},
+    path::Path,
+    process::{Command as StdCommand, ExitStatus, Stdio},
+    thread::{self, sleep},
+    time::{Duration, Instant},
 };
 
+/// What a command printed and how it exited, see [`Cmd::capture`]
+#[derive(Debug)]
+pub struct CmdOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// `None` when the command was stopped by a signal
+    pub code: Option<i32>,
+    pub duration: Duration,
+    /// Set when garlic stopped the command
+    pub stopped: Option<Stopped>,
+}
+
+impl CmdOutput {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+
+    /// The first version number in stdout, e.g. `1.85.0` for `cargo 1.85.0 (d73d2caf9 2024-12-31)`
+    pub fn version(&self) -> Option<&str> {
+        parse_version(&self.stdout)
+    }
+}
+
+/// Finds the first word that looks like a version number in the output of `<tool> --version`
+pub fn parse_version(output: &str) -> Option<&str> {
+    output
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('v').trim_end_matches(','))
+        .find(|word| {
+            word.contains('.') && word.chars().next().is_some_and(|ch| ch.is_ascii_digit())
+        })
+}
+
+/// Why a command didn't succeed, see [`Cmd::check`]
+#[derive(Debug)]
+pub enum CmdError {
+    /// The command couldn't be started, e.g. because it isn't installed
+    Spawn(io::Error),
+    /// The command exited on its own with a non-zero code
+    Failed { command: String, code: Option<i32> },
+    /// garlic stopped the command
+    Stopped { command: String, stopped: Stopped },
+}
+
+impl CmdError {
+    /// The type shown by [`error`]
+    pub fn kind(&self) -> String {
+        match self {
+            CmdError::Spawn(e) => e.kind().to_string(),
+            CmdError::Failed { .. } => "failed".to_owned(),
+            CmdError::Stopped { .. } => "terminated".to_owned(),
+        }
+    }
+
+    /// The exit code garlic ends with when it gives up because of this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CmdError::Failed { code, .. } => code.unwrap_or(1),
+            CmdError::Stopped {
+                stopped: Stopped::Interrupted,
+                ..
+            } => 130,
+            _ => 1,
+        }
+    }
+
+    /// Exits garlic like [`Cmd::req`] does when the command fails
+    pub fn exit(&self) -> ! {
+        match self {
+            CmdError::Spawn(e) => error(e.kind(), e),
+            CmdError::Stopped { .. } => {
+                error_opt(self.kind(), self);
+                garlic_exit(self.exit_code())
+            }
+            // The command printed why it failed itself
+            CmdError::Failed { .. } => garlic_exit(self.exit_code()),
+        }
+    }
+}
+
+impl Display for CmdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CmdError::Spawn(e) => write!(f, "{e}"),
+            CmdError::Failed {
+                command,
+                code: Some(code),
+            } => write!(f, "\"{command}\" exited with code {code}"),
+            CmdError::Failed { command, .. } => write!(f, "\"{command}\" was killed"),
+            CmdError::Stopped { command, stopped } => write!(f, "\"{command}\" {stopped}"),
+        }
+    }
+}
+
+/// Where the output of a running command goes
+#[derive(Clone, Copy, PartialEq)]
+enum Streams {
+    /// Straight to the terminal, which the command owns while it runs
+    Inherit,
+    /// Separately into [`CmdOutput`]
+    Capture,
+    /// Interleaved into `stdout`, for a pipeline step that runs next to others
+    Merge,
+}
+
+/// Keeps tools colorful when their output goes through garlic before reaching the terminal
+const FORCE_COLOR: [(&str, &str); 3] = [
+    ("CARGO_TERM_COLOR", "always"),
+    ("CLICOLOR_FORCE", "1"),
+    ("FORCE_COLOR", "1"),
+];
+
+struct Execution {
+    status: ExitStatus,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    duration: Duration,
+    stopped: Option<Stopped>,
+}
+
 #[must_use]
 pub struct Cmd {
     inner: StdCommand,
     display: String,
-    return_dir: PathBuf,
+    timeout: Option<Duration>,
+    read_only: bool,
 }
 
 impl Cmd {
+    /// Splits `command` like a shell would, so arguments can be quoted: `cargo test -- 'a b'`
     pub fn run(command: impl AsRef<str>) -> Self {
-        let command = command.as_ref();
-        let mut args = command.split(" ");
-        let return_dir = current_dir().expect("Expected to be in a valid directory");
-
-        let cmd = args.next().expect("Expected command to not be empty");
-        let dir = match find_dotgarlic_directory() {
-            Some(dir) => dir,
-            None => return_dir.clone(),
-        };
+        let mut words = split_command(command.as_ref()).into_iter();
+        let program = words
+            .next()
+            .unwrap_or_else(|| error("invalid_command", "Expected command to not be empty"));
+
+        Self::new(program, words)
+    }
+
+    /// Runs `program` with `args` passed on as-is
+    pub fn new<I, S>(program: impl AsRef<OsStr>, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let program = program.as_ref();
+        let display = quote(&program.to_string_lossy()).into_owned();
+
+        Self::from_command(StdCommand::new(program), display).args(args)
+    }
 
-        let mut cmd = StdCommand::new(cmd);
-        cmd.args(args).current_dir(dir);
+    /// Runs a command of this garlic binary in a separate process
+    pub fn garlic(command: impl AsRef<str>) -> Self {
+        let exe = env::current_exe().expect("Expected to find the garlic executable");
+        Self::from_command(StdCommand::new(exe), "garlic").args(split_command(command.as_ref()))
+    }
 
+    fn from_command(inner: StdCommand, display: impl Into<String>) -> Self {
         Self {
-            inner: cmd,
-            display: command.to_owned(),
-            return_dir,
+            inner,
+            display: display.into(),
+            timeout: None,
+            read_only: false,
         }
     }
 
-    pub fn arg(mut self, arg: impl Display + AsRef<OsStr>) -> Self {
-        self.display.extend(format!(" {arg}").chars());
+    /// Runs the command in `dir` instead of the current directory, see [`Project::cmd`]
+    ///
+    /// [`Project::cmd`]: super::Project::cmd
+    pub fn dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.inner.current_dir(dir);
+        self
+    }
+
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.push_display(arg.as_ref());
         self.inner.arg(arg);
         self
     }
@@ -46,78 +200,292 @@ impl Cmd {
     pub fn args<I, S>(mut self, args: I) -> Self
     where
         I: IntoIterator<Item = S>,
-        S: Display + AsRef<OsStr>,
+        S: AsRef<OsStr>,
     {
-        let args = args.into_iter().map(|arg| {
-            self.display.extend(format!(" {arg}").chars());
-            arg
-        });
+        for arg in args {
+            self = self.arg(arg);
+        }
+
+        self
+    }
 
-        self.inner.args(args);
+    fn push_display(&mut self, arg: &OsStr) {
+        self.display.push(' ');
+        self.display.push_str(&quote(&arg.to_string_lossy()));
+    }
+
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.inner.env(key, value);
+        self
+    }
+
+    /// Stops the command (and everything it started) when it runs longer than `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 
+    /// Marks a command that only looks things up, so [`Cmd::capture`] runs it even with `--dry-run`
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
         self
     }
 
+    /// The command as it would be typed in a shell
+    pub fn command_line(&self) -> &str {
+        &self.display
+    }
+
     fn display(&self) {
-        println!(
+        print_line(format!(
             "{}: Running \"{}\"",
             "[garlic]".green(),
             self.display.cyan(),
-        );
+        ));
     }
 
-    pub fn app(self) -> Self {
-        self.push_path("app")
+    pub fn req(self) {
+        if let Err(e) = self.check() {
+            e.exit()
+        }
     }
 
-    pub fn export_fns(self) -> Self {
-        self.push_path("export_fns")
+    pub fn opt(self) {
+        if let Err(e @ (CmdError::Spawn(_) | CmdError::Stopped { .. })) = self.check() {
+            error_opt(e.kind(), e)
+        }
     }
 
-    fn push_path(mut self, dir: &str) -> Self {
-        self.inner.current_dir(
-            self.inner
-                .get_current_dir()
-                .expect("Expected to get a current working directory when moving to app")
-                .join(dir),
-        );
-        self
+    /// Always `true` with `--dry-run`
+    pub fn ok(self) -> bool {
+        match self.check() {
+            Ok(()) => true,
+            Err(e @ CmdError::Stopped { .. }) => {
+                error_opt(e.kind(), e);
+                false
+            }
+            Err(_) => false,
+        }
     }
 
-    pub fn req(mut self) {
+    /// Runs the command like [`Cmd::req`], but returns why it failed instead of exiting
+    pub fn check(mut self) -> Result<(), CmdError> {
+        if dry_run() {
+            self.print_plan();
+            return Ok(());
+        }
+
         self.display();
-        let status = self.inner.status();
-        self.return_to_dir();
+        let streams = match buffering_output() {
+            true => Streams::Merge,
+            false => Streams::Inherit,
+        };
+        let execution = self.execute(streams);
+        self.log(&execution);
 
-        match status {
-            Err(e) => error(e.kind(), e),
-            Ok(o) if !o.success() => exit(o.code().unwrap_or(1)),
-            _ => {}
+        let execution = execution.map_err(CmdError::Spawn)?;
+        if streams == Streams::Merge {
+            print_output(&String::from_utf8_lossy(&execution.stdout));
+        }
+        match execution.stopped {
+            Some(stopped) => Err(CmdError::Stopped {
+                command: self.display,
+                stopped,
+            }),
+            None if !execution.status.success() => Err(CmdError::Failed {
+                command: self.display,
+                code: execution.status.code(),
+            }),
+            None => Ok(()),
         }
     }
 
-    pub fn opt(mut self) {
-        self.display();
-        match self.inner.status() {
-            Err(e) => error_opt(e.kind(), e),
-            _ => {}
+    /// Runs the command without printing anything, collecting its output instead.
+    /// With `--dry-run` the output is empty, unless the command is [`Cmd::read_only`].
+    pub fn capture(mut self) -> io::Result<CmdOutput> {
+        if dry_run() && !self.read_only {
+            self.print_plan();
+            return Ok(CmdOutput {
+                stdout: String::new(),
+                stderr: String::new(),
+                code: Some(0),
+                duration: Duration::ZERO,
+                stopped: None,
+            });
         }
 
-        self.return_to_dir();
+        let execution = self.execute(Streams::Capture);
+        self.log(&execution);
+        let execution = execution?;
+
+        Ok(CmdOutput {
+            stdout: String::from_utf8_lossy(&execution.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&execution.stderr).into_owned(),
+            code: execution.status.code(),
+            duration: execution.duration,
+            stopped: execution.stopped,
+        })
     }
 
-    pub fn ok(mut self) -> bool {
-        self.display();
-        let ok = self.inner.status().is_ok_and(|o| o.success());
+    /// Adds the command and how it went to the log of this run
+    fn log(&self, execution: &io::Result<Execution>) {
+        let dir = self.inner.get_current_dir();
+        match execution {
+            Ok(execution) => {
+                let result = match (execution.stopped, execution.status.code()) {
+                    (Some(stopped), _) => stopped.to_string(),
+                    (None, Some(code)) => code.to_string(),
+                    (None, None) => "killed".to_owned(),
+                };
+                log_run(&self.display, dir, result, execution.duration)
+            }
+            Err(e) => log_run(
+                &self.display,
+                dir,
+                format!("couldn't start: {e}"),
+                Duration::ZERO,
+            ),
+        }
+    }
 
-        self.return_to_dir();
-        ok
+    /// What `--dry-run` prints instead of running the command
+    fn print_plan(&self) {
+        dry_run_print(format!("Would run \"{}\"", self.display.cyan()));
+        print_line(format!(
+            "    program: {}",
+            self.inner.get_program().to_string_lossy()
+        ));
+        print_line(format!(
+            "    args: {}",
+            join(self.inner.get_args().map(|arg| arg.to_string_lossy()))
+        ));
+        if let Some(dir) = self.inner.get_current_dir() {
+            print_line(format!("    directory: {}", dir.display()));
+        }
+        for (key, value) in self.inner.get_envs() {
+            match value {
+                Some(value) => print_line(format!(
+                    "    env: {}={}",
+                    key.to_string_lossy(),
+                    value.to_string_lossy()
+                )),
+                None => print_line(format!("    env: {} (removed)", key.to_string_lossy())),
+            }
+        }
+        if let Some(timeout) = self.timeout {
+            print_line(format!("    timeout: {timeout:?}"));
+        }
     }
 
-    fn return_to_dir(&self) {
-        match env::set_current_dir(&self.return_dir) {
-            Ok(_) => {}
-            Err(e) => panic!("Failed to change directory: {}", e),
+    /// Runs the command in its own process group, which is cleaned up afterwards
+    fn execute(&mut self, streams: Streams) -> io::Result<Execution> {
+        let start = Instant::now();
+        let mut merged = None;
+
+        match streams {
+            Streams::Inherit => {}
+            Streams::Capture => {
+                self.inner
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped());
+            }
+            Streams::Merge => {
+                let (reader, writer) = io::pipe()?;
+                self.inner.stdin(Stdio::null());
+                self.inner.stdout(writer.try_clone()?).stderr(writer);
+                merged = Some(reader);
+
+                if io::stdout().is_terminal() {
+                    for (key, value) in FORCE_COLOR {
+                        if env::var_os(key).is_none() {
+                            self.inner.env(key, value);
+                        }
+                    }
+                }
+            }
+        }
+
+        let foreground = process::prepare(&mut self.inner, streams == Streams::Inherit);
+        let spawned = self.inner.spawn();
+        if merged.is_some() {
+            // Closes garlic's end of the pipe, so reading stops when the command exits
+            self.inner.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+        let mut child = spawned?;
+        let group = process::started(&child, foreground);
+
+        let stdout = match merged {
+            Some(reader) => Some(read_in_background(reader)),
+            None => child.stdout.take().map(read_in_background),
+        };
+        let stderr = child.stderr.take().map(read_in_background);
+
+        let mut stopped = None;
+        let status = match self.timeout {
+            None => child.wait()?,
+            Some(timeout) => loop {
+                if let Some(status) = child.try_wait()? {
+                    break status;
+                }
+
+                if start.elapsed() > timeout {
+                    stopped = Some(Stopped::TimedOut(timeout));
+                    group.terminate(&mut child);
+                    break child.wait()?;
+                }
+
+                sleep(Duration::from_millis(20));
+            },
+        };
+
+        if stopped.is_none() && group.interrupted_by(&status) {
+            stopped = Some(Stopped::Interrupted);
         }
+        drop(group);
+
+        let join = |reader: Option<thread::JoinHandle<Vec<u8>>>| {
+            reader
+                .map(|reader| reader.join().unwrap_or_default())
+                .unwrap_or_default()
+        };
+
+        Ok(Execution {
+            status,
+            stdout: join(stdout),
+            stderr: join(stderr),
+            duration: start.elapsed(),
+            stopped,
+        })
     }
 }
+
+fn split_command(command: &str) -> Vec<String> {
+    split(command).unwrap_or_else(|e| {
+        error(
+            "invalid_command",
+            format!("Couldn't parse \"{command}\": {e}"),
+        )
+    })
+}
+
+/// Reads `pipe` until the command closes it
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let mut chunk = [0; 8192];
+
+        loop {
+            let read = match pipe.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+
+        buffer
+    })
+}
@@ -1,18 +1,105 @@
+use super::run_log::{log_error, log_exit};
 use colored::Colorize;
 use rand::{distr::Alphanumeric, rng, Rng};
 use std::{
-    env::{self},
+    cell::RefCell,
     fmt::Display,
     fs::{self},
     path::{Path, PathBuf},
-    process::exit,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
 };
 
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+type Cleanup = Box<dyn FnOnce() + Send>;
+
+/// Cleanups that still have to run when garlic exits early, see [`on_exit`]
+static EXIT_HOOKS: Mutex<Vec<(u64, Cleanup)>> = Mutex::new(Vec::new());
+static NEXT_EXIT_HOOK: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Collects what a parallel pipeline step prints, see [`buffer_output`]
+    static STEP_OUTPUT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Collects everything this thread prints, including the output of commands,
+/// instead of printing it until [`take_output`] is called
+pub fn buffer_output() {
+    STEP_OUTPUT.with_borrow_mut(|output| *output = Some(String::new()));
+}
+
+pub fn buffering_output() -> bool {
+    STEP_OUTPUT.with_borrow(Option::is_some)
+}
+
+/// Stops collecting and returns what was printed since [`buffer_output`]
+pub fn take_output() -> String {
+    STEP_OUTPUT
+        .with_borrow_mut(Option::take)
+        .unwrap_or_default()
+}
+
+/// Appends raw output, such as that of a command, to the collected output,
+/// or prints it when nothing is collecting
+pub fn print_output(content: &str) {
+    let buffered = STEP_OUTPUT.with_borrow_mut(|output| match output {
+        Some(output) => {
+            output.push_str(content);
+            true
+        }
+        None => false,
+    });
+
+    if !buffered {
+        print!("{content}");
+    }
+}
+
+/// `println!` that respects [`buffer_output`]
+pub fn print_line(content: impl Display) {
+    print_output(&format!("{content}\n"));
+}
+
 pub fn garlic_print(content: impl Display) {
-    println!("{}: {}", "[garlic]".green(), content);
+    print_line(format!("{}: {}", "[garlic]".green(), content));
+}
+
+/// Set by `--dry-run`: commands and file changes are only printed
+pub fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+pub fn dry_run_print(content: impl Display) {
+    print_line(format!("{}: {}", "[dry-run]".yellow(), content));
+}
+
+/// `fs::copy` that only reports the copy with `--dry-run`
+pub fn copy_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<(), std::io::Error> {
+    if dry_run() {
+        dry_run_print(format!(
+            "Would copy {} to {}",
+            from.as_ref().display(),
+            to.as_ref().display()
+        ));
+        return Ok(());
+    }
+
+    fs::copy(from, to).map(|_| ())
 }
 
 pub fn error(kind: impl Display, message: impl Display) -> ! {
+    // Don't lose what a pipeline step printed before it failed
+    if buffering_output() {
+        print!("{}", take_output());
+    }
     println!(
         "{} (type {}): {}",
         "Error".red(),
@@ -20,16 +107,98 @@ pub fn error(kind: impl Display, message: impl Display) -> ! {
         message.to_string().red()
     );
 
-    exit(1)
+    log_error(&kind.to_string(), &message.to_string());
+    garlic_exit(1)
+}
+
+/// Runs `cleanup` if garlic exits through [`garlic_exit`] before the returned hook is dropped
+pub fn on_exit(cleanup: impl FnOnce() + Send + 'static) -> ExitHook {
+    let id = NEXT_EXIT_HOOK.fetch_add(1, Ordering::Relaxed);
+    if let Ok(mut hooks) = EXIT_HOOKS.lock() {
+        hooks.push((id, Box::new(cleanup)));
+    }
+    ExitHook(id)
+}
+
+/// Removes its cleanup from [`on_exit`] when dropped
+#[must_use]
+pub struct ExitHook(u64);
+
+impl Drop for ExitHook {
+    fn drop(&mut self) {
+        if let Ok(mut hooks) = EXIT_HOOKS.lock() {
+            hooks.retain(|(id, _)| *id != self.0);
+        }
+    }
+}
+
+/// Exits garlic, running the [`on_exit`] cleanups and finishing its log first
+pub fn garlic_exit(code: i32) -> ! {
+    let hooks = EXIT_HOOKS
+        .lock()
+        .map(|mut hooks| std::mem::take(&mut *hooks))
+        .unwrap_or_default();
+    for (_, cleanup) in hooks.into_iter().rev() {
+        cleanup();
+    }
+
+    log_exit(code);
+    std::process::exit(code)
 }
 
 pub fn error_opt(kind: impl Display, message: impl Display) {
-    println!(
+    print_line(format!(
         "{} (type {}): {}",
         "Error".red(),
         kind.to_string().cyan(),
         message.to_string().red()
+    ));
+    log_error(&kind.to_string(), &message.to_string());
+}
+
+/// Like [`error_opt`] for problems that don't stop garlic
+pub fn warning(kind: impl Display, message: impl Display) {
+    print_line(format!(
+        "{} (type {}): {}",
+        "Warning".yellow(),
+        kind.to_string().cyan(),
+        message.to_string().yellow()
+    ));
+}
+
+/// Seconds since the unix epoch
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Formats seconds since the unix epoch as a UTC `YYYYMMDDHHMMSS`
+pub fn utc_timestamp(unix_seconds: i64) -> i64 {
+    let (days, seconds) = (
+        unix_seconds.div_euclid(86400),
+        unix_seconds.rem_euclid(86400),
     );
+
+    // Converts days since the epoch to a civil date, see https://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    year * 10_000_000_000
+        + month * 100_000_000
+        + day * 1_000_000
+        + (seconds / 3600) * 10_000
+        + (seconds % 3600 / 60) * 100
+        + seconds % 60
 }
 
 pub fn folder_empty<S: AsRef<Path>>(location: S) -> bool {
@@ -38,7 +207,7 @@ pub fn folder_empty<S: AsRef<Path>>(location: S) -> bool {
     }
 
     let entries = fs::read_dir(location).expect("Expected a folder");
-    return entries.count() == 0;
+    entries.count() == 0
 }
 
 pub fn random_jwt_secret() -> String {
@@ -70,7 +239,7 @@ pub fn copy_dir_contents(
         } else {
             output_root.join(&src)
         };
-        if fs::metadata(&dest).is_err() {
+        if fs::metadata(&dest).is_err() && !dry_run() {
             fs::create_dir_all(&dest)?;
         }
 
@@ -83,6 +252,15 @@ pub fn copy_dir_contents(
                 match path.file_name() {
                     Some(filename) => {
                         let dest_path = dest.join(filename);
+                        if dry_run() {
+                            dry_run_print(format!(
+                                "Would copy {:?} to {}",
+                                &path.strip_prefix(from).unwrap(),
+                                dest_path.display()
+                            ));
+                            continue;
+                        }
+
                         garlic_print(format!("copying {:?}", &path.strip_prefix(from).unwrap()));
                         fs::copy(&path, &dest_path)?;
                     }
@@ -97,19 +275,26 @@ pub fn copy_dir_contents(
     Ok(())
 }
 
-pub fn find_dotgarlic_directory() -> Option<PathBuf> {
-    let mut current_dir = env::current_dir().ok()?;
+/// The directory garlic keeps a project's state in: `.garlic`, or `.garlic.d` next to it when
+/// `.garlic` is the anchor file of an older project. The anchor itself is never touched.
+pub fn dotgarlic_dir(root: impl AsRef<Path>) -> PathBuf {
+    let dotgarlic = root.as_ref().join(".garlic");
+    match dotgarlic.is_file() {
+        true => root.as_ref().join(".garlic.d"),
+        false => dotgarlic,
+    }
+}
 
-    loop {
-        let garlic_path = current_dir.join(".garlic");
-        if garlic_path.exists() {
-            return Some(current_dir);
-        }
+/// Returns `name` inside [`dotgarlic_dir`], creating the directory if needed.
+/// A new directory ignores the local state, like logs and caches, in git.
+pub fn dotgarlic_path(root: impl AsRef<Path>, name: impl AsRef<Path>) -> PathBuf {
+    let dir = dotgarlic_dir(root);
 
-        if let Some(parent) = current_dir.parent() {
-            current_dir = parent.to_path_buf();
-        } else {
-            return None;
-        }
+    if !dry_run() && !dir.exists() {
+        fs::create_dir_all(&dir).expect("Expected to be able to create the garlic directory");
+        fs::write(dir.join(".gitignore"), "cache/\nlogs/\nsnapshots\n")
+            .expect("Expected to be able to create the garlic directory");
     }
+
+    dir.join(name)
 }
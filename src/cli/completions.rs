@@ -0,0 +1,132 @@
+use super::{GarlicParser, Project};
+use clap::CommandFactory;
+use clap_complete::{
+    engine::CompletionCandidate,
+    env::{CompleteEnv, Shells},
+};
+use std::{
+    env, fs,
+    io::{self, Write},
+};
+use strum::Display;
+
+/// Set by the completion scripts when they ask garlic for candidates
+const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Bash splits words at ':' by default, which breaks completing `db:seed` and friends.
+/// Instead of changing `COMP_WORDBREAKS` for every command, this function splits the line
+/// for garlic only and drops what is already typed before the last ':' from the candidates.
+const BASH_WRAPPER: &str = r#"
+_garlic() {
+    local line=${COMP_LINE:0:COMP_POINT}
+    local -a words
+    read -r -a words <<< "$line"
+    if [[ -z $line || $line == *[[:space:]] ]]; then
+        words+=("")
+    fi
+    local cur=${words[${#words[@]}-1]}
+    local COMP_WORDS=("${words[@]}") COMP_CWORD=$((${#words[@]} - 1))
+    _clap_complete_garlic garlic "$cur" "${words[COMP_CWORD-1]}"
+    if [[ $cur == *:* ]]; then
+        local prefix=${cur%"${cur##*:}"}
+        COMPREPLY=("${COMPREPLY[@]#"$prefix"}")
+    fi
+}
+"#;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Display, clap::ValueEnum)]
+#[strum(serialize_all = "lowercase")]
+pub enum Shell {
+    #[default]
+    Bash,
+    Zsh,
+    Fish,
+    Elvish,
+    Powershell,
+}
+
+/// Answers the completion scripts from [`print_completions`], exits when this run is one of them.
+/// Has to run before anything is printed.
+pub fn complete() {
+    CompleteEnv::with_factory(GarlicParser::command)
+        .var(COMPLETE_VAR)
+        .complete();
+}
+
+/// `garlic completions`: prints the script that sets up completions for `shell`.
+///
+/// The script calls back into garlic for every completion, so candidates like the workspace
+/// packages always match the project in the current directory, or the one given with `-C`.
+pub fn print_completions(shell: Shell) {
+    io::stdout()
+        .write_all(completion_script(shell).as_bytes())
+        .expect("Expected to be able to print the completion script");
+}
+
+/// The script [`print_completions`] prints
+pub fn completion_script(shell: Shell) -> String {
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(&shell.to_string())
+        .unwrap_or_else(|| panic!("Expected completions to support {shell}"));
+
+    let mut script = Vec::new();
+    completer
+        .write_registration(COMPLETE_VAR, "garlic", "garlic", "garlic", &mut script)
+        .expect("Expected to be able to write the completion script");
+    let mut script = String::from_utf8(script).expect("Expected the completion script to be utf-8");
+
+    if shell == Shell::Bash {
+        let registered = "-F _clap_complete_garlic garlic";
+        assert!(
+            script.contains(registered),
+            "Expected the bash script to register _clap_complete_garlic"
+        );
+        script = script.replace(registered, "-F _garlic garlic");
+        script.push_str(BASH_WRAPPER);
+    }
+
+    script
+}
+
+/// The project of the command line being completed, which respects `-C`/`--project`
+fn project() -> Option<Project> {
+    let line = env::args()
+        .skip_while(|arg| arg != "--")
+        .skip(1)
+        .take_while(|arg| arg != "--");
+    Project::try_find(Project::path_arg(line).as_deref())
+}
+
+/// The packages of the cargo workspace, for `add:crate`
+pub fn workspace_packages() -> Vec<CompletionCandidate> {
+    let Some(project) = project() else {
+        return Vec::new();
+    };
+
+    project
+        .workspace_packages()
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// The directories in `seeds/`, for `db:seed --profile`
+pub fn seed_profiles() -> Vec<CompletionCandidate> {
+    let Some(project) = project() else {
+        return Vec::new();
+    };
+
+    let Ok(entries) = fs::read_dir(project.root.join("seeds")) else {
+        return Vec::new();
+    };
+
+    let mut profiles: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    profiles.sort();
+
+    profiles.into_iter().map(CompletionCandidate::new).collect()
+}
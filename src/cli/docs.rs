@@ -0,0 +1,154 @@
+use super::render_help::{
+    argument, command_docs, garlic_command, group_by_category, Category, CommandDoc, Entry,
+};
+use clap::Command;
+
+/// What `garlic help --format` renders
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum HelpFormat {
+    /// A roff man page for `man -l`
+    Man,
+    /// A Markdown reference
+    Markdown,
+}
+
+/// The whole reference of garlic in `format`
+pub fn render_docs(format: HelpFormat) -> String {
+    let cli = garlic_command();
+    let options: Vec<_> = cli.get_arguments().filter_map(argument).collect();
+    let groups = group_by_category(command_docs(&cli));
+
+    match format {
+        HelpFormat::Man => man(&cli, &options, &groups),
+        HelpFormat::Markdown => markdown(&cli, &options, &groups),
+    }
+}
+
+fn markdown(cli: &Command, options: &[Entry], groups: &[(Category, Vec<CommandDoc>)]) -> String {
+    let mut doc = format!(
+        "# {} v{}\n\n",
+        env!("CARGO_BIN_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    if let Some(about) = cli.get_about() {
+        doc.push_str(&format!("{about}\n\n"));
+    }
+
+    doc.push_str(&format!(
+        "Usage: `{} [OPTIONS] <COMMAND>`\n\n## Options\n\n",
+        env!("CARGO_BIN_NAME")
+    ));
+    markdown_entries(&mut doc, options);
+
+    for (category, commands) in groups {
+        doc.push_str(&format!("## {category}\n\n"));
+
+        for command in commands {
+            doc.push_str(&format!("### `{}`\n\n", command.name));
+            doc.push_str(&format!(
+                "{}\n\nUsage: `{}`\n\n",
+                command.description, command.usage
+            ));
+
+            if !command.aliases.is_empty() {
+                let aliases: Vec<_> = command
+                    .aliases
+                    .iter()
+                    .map(|alias| format!("`{alias}`"))
+                    .collect();
+                doc.push_str(&format!("Aliases: {}\n\n", aliases.join(", ")));
+            }
+
+            markdown_entries(&mut doc, &command.arguments);
+        }
+    }
+
+    doc.trim_end().to_owned() + "\n"
+}
+
+fn markdown_entries(doc: &mut String, entries: &[Entry]) {
+    if entries.is_empty() {
+        return;
+    }
+
+    for entry in entries {
+        doc.push_str(&format!("- `{}`: {}\n", entry.name, entry.description));
+    }
+    doc.push('\n');
+}
+
+fn man(cli: &Command, options: &[Entry], groups: &[(Category, Vec<CommandDoc>)]) -> String {
+    let bin = env!("CARGO_BIN_NAME");
+    let mut doc = format!(
+        ".TH {} 1 \"\" \"{} {}\"\n",
+        bin.to_uppercase(),
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    doc.push_str(&format!(
+        ".SH NAME\n{bin} \\- {}\n",
+        roff(
+            &cli.get_about()
+                .map(|about| about.to_string())
+                .unwrap_or_default()
+        )
+    ));
+    doc.push_str(&format!(
+        ".SH SYNOPSIS\n\\fB{bin}\\fR [\\fIOPTIONS\\fR] <\\fICOMMAND\\fR>\n"
+    ));
+
+    doc.push_str(".SH OPTIONS\n");
+    man_entries(&mut doc, options);
+
+    for (category, commands) in groups {
+        doc.push_str(&format!(".SH {}\n", category.to_string().to_uppercase()));
+
+        for command in commands {
+            doc.push_str(&format!(
+                ".TP\n\\fB{}\\fR\n{}\n",
+                roff(&command.usage),
+                roff(&command.description)
+            ));
+
+            if !command.aliases.is_empty() {
+                doc.push_str(&format!(
+                    ".br\nAliases: {}\n",
+                    roff(&command.aliases.join(", "))
+                ));
+            }
+
+            if !command.arguments.is_empty() {
+                doc.push_str(".RS\n");
+                man_entries(&mut doc, &command.arguments);
+                doc.push_str(".RE\n");
+            }
+        }
+    }
+
+    doc
+}
+
+fn man_entries(doc: &mut String, entries: &[Entry]) {
+    for entry in entries {
+        doc.push_str(&format!(
+            ".TP\n\\fB{}\\fR\n{}\n",
+            roff(&entry.name),
+            roff(&entry.description)
+        ));
+    }
+}
+
+/// Escapes text for roff, so that it can't start a request or contain an escape
+fn roff(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\\', "\\e").replace('-', "\\-");
+            match line.starts_with(['.', '\'']) {
+                true => format!("\\&{line}"),
+                false => line,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
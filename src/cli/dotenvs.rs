@@ -1,3 +1,4 @@
+use super::{dry_run, dry_run_print};
 use derived_deref::{Deref, DerefMut};
 use std::{
     collections::HashMap,
@@ -30,13 +31,25 @@ impl DotEnvs {
     }
 
     pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
-        let lines: Vec<String> = self
-            .get_file(path.as_ref())?
-            .lines()
-            .map(|line| line.to_owned())
-            .collect();
-
-        let lines = self.apply_to_lines(lines);
+        // With --dry-run the file might not have been copied yet
+        let original: Vec<String> = if dry_run() && !path.as_ref().exists() {
+            vec![]
+        } else {
+            self.get_file(path.as_ref())?
+                .lines()
+                .map(|line| line.to_owned())
+                .collect()
+        };
+
+        let lines = self.apply_to_lines(original.clone());
+
+        if dry_run() {
+            for line in lines.iter().filter(|line| !original.contains(line)) {
+                let key = line.split('=').next().unwrap_or_default();
+                dry_run_print(format!("Would set {key} in {}", path.as_ref().display()));
+            }
+            return Ok(());
+        }
 
         // // Write content back to file
         let mut file = File::create(path)?;
@@ -59,7 +72,7 @@ impl DotEnvs {
                     }
                 }
 
-                return false;
+                false
             });
 
             if let Some(line) = line {
@@ -0,0 +1,185 @@
+use super::{dotgarlic_dir, dotgarlic_path, dry_run, dry_run_print, error, warning};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+use strum::Display;
+
+/// The project settings in `.garlic/manifest.toml`, or `.garlic.d/` next to an anchor file,
+/// meant to be committed
+pub const MANIFEST_FILE: &str = "manifest.toml";
+
+/// The template `garlic init` copies and `garlic upgrade` follows
+pub const TEMPLATE_REPO: &str = "https://github.com/eatenpancreas/garlic.git";
+
+/// The version of the running garlic
+pub fn garlic_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION"))
+        .expect("Expected the garlic version to be a semver version")
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Manifest {
+    /// Which package manager the frontend uses, bun if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package_manager: Option<PackageManager>,
+    /// The garlic versions that understand the project's layout, e.g. `^0.2.1`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub garlic_version: Option<VersionReq>,
+    /// The commit of the garlic template the project was created from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template_revision: Option<String>,
+}
+
+impl Manifest {
+    /// The manifest of the project at `root`, or the defaults if it has none.
+    /// Exits when the manifest is invalid
+    pub fn load(root: impl AsRef<Path>) -> Self {
+        Manifest::try_load(root).unwrap_or_else(|e| error("invalid_manifest", e))
+    }
+
+    /// Like [`Manifest::load`], but returns why the manifest is invalid
+    pub fn try_load(root: impl AsRef<Path>) -> Result<Self, String> {
+        let path = dotgarlic_dir(root).join(MANIFEST_FILE);
+        match fs::read_to_string(&path) {
+            Ok(content) => Manifest::parse(&content)
+                .map_err(|e| format!("Couldn't read {}: {e}", path.display())),
+            Err(_) => Ok(Manifest::default()),
+        }
+    }
+
+    /// A manifest for a new project made with this garlic
+    pub fn new(package_manager: PackageManager, template_revision: Option<String>) -> Self {
+        Manifest {
+            package_manager: Some(package_manager),
+            garlic_version: Some(
+                VersionReq::parse(&format!("^{}", garlic_version()))
+                    .expect("Expected the garlic version to make a version range"),
+            ),
+            template_revision,
+        }
+    }
+
+    /// Whether `version` of garlic is in the project's `garlic-version` range, true without one
+    pub fn supports(&self, version: &Version) -> bool {
+        self.garlic_version
+            .as_ref()
+            .is_none_or(|range| range.matches(version))
+    }
+
+    /// Warns when the running garlic is outside of `garlic-version`, or exits if `strict`
+    pub fn check_garlic_version(&self, strict: bool) {
+        let current = garlic_version();
+        let Some(range) = self
+            .garlic_version
+            .as_ref()
+            .filter(|_| !self.supports(&current))
+        else {
+            return;
+        };
+
+        let message = format!(
+            "This project was made for garlic {range}, but this is garlic {current}. \
+            Install a matching version with `garlic update --version <VERSION>`, \
+            or change garlic-version in {MANIFEST_FILE} once the project is updated"
+        );
+        match strict {
+            true => error("incompatible_garlic", message),
+            false => warning("incompatible_garlic", message),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    pub fn to_file_string(&self) -> String {
+        toml::to_string(self).expect("Expected the manifest to serialize")
+    }
+
+    /// Writes the manifest into the [`dotgarlic_dir`] at `root`
+    pub fn save(&self, root: impl AsRef<Path>) {
+        let path = dotgarlic_path(root, MANIFEST_FILE);
+        if dry_run() {
+            dry_run_print(format!("Would write {}", path.display()));
+            return;
+        }
+
+        fs::write(&path, self.to_file_string()).expect("Expected to be able to save the manifest");
+    }
+}
+
+/// A JavaScript package manager for the frontend, see `--pm`
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Display, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum PackageManager {
+    #[default]
+    Bun,
+    Pnpm,
+    Npm,
+    Yarn,
+}
+
+impl PackageManager {
+    pub fn program(self) -> &'static str {
+        match self {
+            PackageManager::Bun => "bun",
+            PackageManager::Pnpm => "pnpm",
+            PackageManager::Npm => "npm",
+            PackageManager::Yarn => "yarn",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PackageManager::Bun => "Bun",
+            PackageManager::Pnpm => "pnpm",
+            PackageManager::Npm => "npm",
+            PackageManager::Yarn => "Yarn",
+        }
+    }
+
+    /// Installs the dependencies in `package.json`
+    pub fn install(self) -> &'static str {
+        match self {
+            PackageManager::Bun => "bun install",
+            PackageManager::Pnpm => "pnpm install",
+            PackageManager::Npm => "npm install",
+            PackageManager::Yarn => "yarn install",
+        }
+    }
+
+    /// The prefix that runs a binary of an installed package, like `vite`
+    pub fn exec(self) -> &'static str {
+        match self {
+            PackageManager::Bun => "bun x",
+            PackageManager::Pnpm => "pnpm exec",
+            PackageManager::Npm => "npx",
+            PackageManager::Yarn => "yarn",
+        }
+    }
+
+    /// The prefix that runs the binary of a package that may not be installed, like
+    /// `openapi-zod-client`, downloading it when needed
+    pub fn dlx(self) -> &'static str {
+        match self {
+            PackageManager::Bun => "bun x",
+            PackageManager::Pnpm => "pnpm dlx",
+            PackageManager::Npm => "npx --yes",
+            PackageManager::Yarn => "yarn dlx",
+        }
+    }
+
+    /// The prefix that runs a script of `package.json`, like `build`
+    pub fn run(self) -> &'static str {
+        match self {
+            PackageManager::Bun => "bun run",
+            PackageManager::Pnpm => "pnpm run",
+            PackageManager::Npm => "npm run",
+            PackageManager::Yarn => "yarn run",
+        }
+    }
+}
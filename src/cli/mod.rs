@@ -1,13 +1,38 @@
+mod cache;
 mod cmd;
 mod common;
+pub mod completions;
+mod docs;
 mod dotenvs;
+mod manifest;
+mod pipeline;
 mod postgres_url;
+mod process;
+mod project;
 mod render_help;
+pub mod run_log;
+pub mod self_update;
+pub mod shell_words;
+mod wrappers;
 
-use clap::command;
-use strum::{Display, EnumIter};
+use clap_complete::engine::ArgValueCandidates;
+use completions::Shell;
+use std::path::PathBuf;
+use strum::{Display, EnumIter, EnumProperty};
 pub use {
-    cmd::Cmd, common::*, dotenvs::DotEnvs, postgres_url::PostgresUrl, render_help::print_info,
+    cache::{fingerprint, Cache},
+    cmd::{parse_version, Cmd, CmdError, CmdOutput},
+    common::*,
+    docs::{render_docs, HelpFormat},
+    dotenvs::DotEnvs,
+    manifest::{garlic_version, Manifest, PackageManager, MANIFEST_FILE, TEMPLATE_REPO},
+    pipeline::{format_duration, Pipeline, PipelineArgs, StepStatus},
+    postgres_url::PostgresUrl,
+    process::{defer_interrupts, interrupted, Stopped},
+    project::Project,
+    render_help::{edit_distance, suggest_commands, wrap, Category, Suggestion},
+    self_update::UpdateSource,
+    wrappers::PassThrough,
 };
 
 #[derive(clap::Parser)]
@@ -21,113 +46,299 @@ pub struct GarlicParser {
     /// Print help
     #[arg(short = 'h', long, global = true)]
     pub help: bool,
+    /// Print the commands and file changes instead of running them
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// Work on the project at this path instead of the one in the current directory
+    #[arg(short = 'C', long, global = true, value_name = "PATH")]
+    pub project: Option<PathBuf>,
+    /// The package manager for the frontend, instead of the one in the manifest
+    #[arg(long, global = true, value_name = "PM")]
+    pub pm: Option<PackageManager>,
+    /// Refuse to run when garlic is outside the project's garlic-version range
+    #[arg(long, global = true)]
+    pub strict_version: bool,
 }
 
-#[derive(clap::Subcommand, Display, EnumIter)]
+#[derive(clap::Subcommand, Display, EnumIter, EnumProperty)]
 pub enum GarlicCommand {
     /// Prints this message
     #[command(name = "help")]
     #[strum(serialize = "help")]
-    Help,
-    /// Detailed CLI information
+    #[strum(props(category = "project"))]
+    Help {
+        /// Only show the commands of this category
+        category: Option<Category>,
+        /// Print the reference of every command as a man page or Markdown instead
+        #[arg(long)]
+        format: Option<HelpFormat>,
+    },
+    /// Detailed information about garlic, the toolchain and the project, e.g. for bug reports
     #[command(name = "info")]
     #[strum(serialize = "info")]
-    Info,
+    #[strum(props(category = "project"))]
+    Info {
+        /// Print the information as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Shows the latest garlic runs in this project, or everything about one of them
+    #[command(name = "log", visible_aliases=["logs"])]
+    #[strum(serialize = "log")]
+    #[strum(props(category = "project"))]
+    Log {
+        /// Which run to show, 1 is the latest. The output of a failed step is only kept when
+        /// it ran next to other steps, a step that runs alone prints to the terminal instead
+        run: Option<usize>,
+        /// How many runs to list
+        #[arg(long, short = 'n', default_value_t = 10)]
+        limit: usize,
+    },
+    /// Prints the script that sets up tab completion, e.g. `garlic completions bash >> ~/.bashrc`
+    #[command(name = "completions")]
+    #[strum(serialize = "completions")]
+    #[strum(props(category = "project"))]
+    Completions {
+        /// The shell to print the script for
+        shell: Shell,
+    },
+    /// Updates garlic to the latest version, showing what changed since the running one
+    #[command(name = "update:self", visible_aliases=["update"])]
+    #[strum(serialize = "update:self")]
+    #[strum(props(category = "project"))]
+    UpdateSelf {
+        /// Install this version instead of the latest one, e.g. 0.3.0
+        #[arg(long)]
+        version: Option<semver::Version>,
+        /// Where to look for versions: crates-io, index:<URL>, git:<URL> or path:<DIR>.
+        /// Defaults to $GARLIC_UPDATE_SOURCE, then crates-io
+        #[arg(long)]
+        source: Option<UpdateSource>,
+        /// Install even if garlic is already at that version
+        #[arg(long)]
+        force: bool,
+        /// Passed on to `cargo install`
+        #[command(flatten)]
+        args: PassThrough,
+    },
     /// Sets up a new project
     #[command(name = "init")]
     #[strum(serialize = "init")]
+    #[strum(props(category = "project"))]
     Init {
         /// If set, the relative location of the project
         location: Option<String>,
     },
+    /// Applies the changes of the garlic template since the project was made or last upgraded
+    #[command(name = "upgrade")]
+    #[strum(serialize = "upgrade")]
+    #[strum(props(category = "project"))]
+    Upgrade {
+        /// The template revision to upgrade to, e.g. a tag or commit
+        #[arg(long, value_name = "REV")]
+        to: Option<String>,
+        /// The template revision the project is on, instead of the one in the manifest
+        #[arg(long, value_name = "REV")]
+        from: Option<String>,
+        /// The git repository of the template
+        #[arg(long, value_name = "URL", default_value = TEMPLATE_REPO)]
+        template: String,
+    },
     /// Sets up the database for an existing project
     #[command(name = "init:db")]
     #[strum(serialize = "init:db")]
+    #[strum(props(category = "database"))]
     InitDb,
+    /// Copies the development database into a named snapshot
+    #[command(name = "db:snapshot")]
+    #[strum(serialize = "db:snapshot")]
+    #[strum(props(category = "database"))]
+    DbSnapshot {
+        /// The name of the snapshot
+        name: String,
+        /// Overwrite the snapshot if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Resets the development database to a named snapshot
+    #[command(name = "db:restore")]
+    #[strum(serialize = "db:restore")]
+    #[strum(props(category = "database"))]
+    DbRestore {
+        /// The name of the snapshot
+        name: String,
+    },
+    /// Runs the SQL files in `seeds/` that haven't run yet, in one transaction
+    #[command(name = "db:seed", visible_aliases=["seed"])]
+    #[strum(serialize = "db:seed")]
+    #[strum(props(category = "database"))]
+    DbSeed {
+        /// Also run the seeds in `seeds/<PROFILE>`
+        #[arg(long, add = ArgValueCandidates::new(completions::seed_profiles))]
+        profile: Option<String>,
+        /// Truncate all tables first and run every seed again
+        #[arg(long)]
+        fresh: bool,
+    },
+    /// Lists the snapshots of the development database
+    #[command(name = "db:snapshots", visible_aliases=["db:snapshot:list"])]
+    #[strum(serialize = "db:snapshots")]
+    #[strum(props(category = "database"))]
+    DbSnapshots,
+    /// Deletes a snapshot of the development database
+    #[command(name = "db:snapshot:delete")]
+    #[strum(serialize = "db:snapshot:delete")]
+    #[strum(props(category = "database"))]
+    DbSnapshotDelete {
+        /// The name of the snapshot
+        name: String,
+    },
     /// Tests the backend, runs `cargo spec` to make sure the frontend is in sync and then tests the frontend
     #[command(name = "test:all", visible_aliases=["test"])]
     #[strum(serialize = "test:all")]
-    TestAll,
+    #[strum(props(category = "testing"))]
+    TestAll {
+        /// Test the backend against a throwaway database that is dropped afterwards
+        #[arg(long)]
+        isolated: bool,
+        /// Regenerate the wasm bundle and client even when their inputs haven't changed
+        #[arg(long)]
+        force: bool,
+        #[command(flatten)]
+        pipeline: PipelineArgs,
+    },
     /// Gets the openapi spec from the api and generates the frontend typescript implementation and routes
     #[command(name = "spec")]
     #[strum(serialize = "spec")]
-    Spec,
+    #[strum(props(category = "codegen"))]
+    Spec {
+        /// Regenerate the wasm bundle and client even when their inputs haven't changed
+        #[arg(long)]
+        force: bool,
+        #[command(flatten)]
+        pipeline: PipelineArgs,
+    },
     /// Exports the functions in export_fns to typescript
     #[command(name = "update:fns", visible_aliases=["fns"])]
     #[strum(serialize = "update:fns")]
-    UpdateFns,
+    #[strum(props(category = "codegen"))]
+    UpdateFns {
+        /// Build even when export_fns hasn't changed since the last build
+        #[arg(long)]
+        force: bool,
+    },
     /// builds the frontend and backend.
     #[command(name = "build")]
     #[strum(serialize = "build")]
-    Build,
+    #[strum(props(category = "build"))]
+    Build {
+        #[command(flatten)]
+        pipeline: PipelineArgs,
+    },
+    /// Applies the pending migrations in `migrations/`
+    #[command(name = "migrate:apply", visible_aliases=["migrate:up"])]
+    #[strum(serialize = "migrate:apply")]
+    #[strum(props(category = "database"))]
+    MigrateApply {
+        /// Only apply migrations up to and including this version
+        #[arg(long)]
+        target_version: Option<i64>,
+        /// Don't fail when applied migrations are missing from `migrations/`
+        #[arg(long)]
+        ignore_missing: bool,
+    },
+    /// Reverts the last applied migration
+    #[command(name = "migrate:revert", visible_aliases=["migrate:down"])]
+    #[strum(serialize = "migrate:revert")]
+    #[strum(props(category = "database"))]
+    MigrateRevert {
+        /// Revert every migration after this version instead
+        #[arg(long)]
+        target_version: Option<i64>,
+        /// Don't fail when applied migrations are missing from `migrations/`
+        #[arg(long)]
+        ignore_missing: bool,
+    },
+    /// Lists the migrations and whether they are applied
+    #[command(name = "migrate:list", visible_aliases=["migrate:info"])]
+    #[strum(serialize = "migrate:list")]
+    #[strum(props(category = "database"))]
+    MigrateList,
+    /// Creates a new reversible migration in `migrations/`
+    #[command(name = "migrate:new", visible_aliases=["migrate:add"])]
+    #[strum(serialize = "migrate:new")]
+    #[strum(props(category = "database"))]
+    MigrateNew {
+        /// What the migration does, e.g. 'create users'
+        name: String,
+        /// Create a single migration that can't be reverted
+        #[arg(long)]
+        simple: bool,
+        /// Open the new files in $EDITOR
+        #[arg(long, short)]
+        edit: bool,
+    },
 
     // -- Wrappers --
-    /// <WRAPPER>Wrapper for 'cargo run'
+    /// Wrapper for 'cargo run'
     #[command(name = "run:backend", visible_aliases=["backend", "server", "run:server"])]
     #[strum(serialize = "run:backend")]
+    #[strum(props(category = "wrappers"))]
     RunBackend {
-        /// Pass in arguments for 'cargo run'
-        #[arg(long, allow_hyphen_values = true, num_args = 0..)]
-        args: Vec<String>,
-    },
-    /// <WRAPPER>Wrapper for 'cargo install garlic-cli'
-    #[command(name = "update:self", visible_aliases=["update"])]
-    #[strum(serialize = "run:backend")]
-    UpdateSelf {
-        /// Pass in arguments for 'cargo install garlic-cli'
-        #[arg(long, allow_hyphen_values = true, num_args = 0..)]
-        args: Vec<String>,
+        #[command(flatten)]
+        args: PassThrough,
     },
-    /// <WRAPPER>Wrapper for 'bun x vite dev'
+    /// Wrapper for 'vite dev' through the package manager
     #[command(name = "run:frontend", visible_aliases=["frontend", "dev"])]
     #[strum(serialize = "run:frontend")]
+    #[strum(props(category = "wrappers"))]
     RunFrontend {
-        /// Pass in arguments for 'bun x vite dev'
-        #[arg(long, allow_hyphen_values = true, num_args = 0..)]
-        args: Vec<String>,
+        #[command(flatten)]
+        args: PassThrough,
     },
-    /// <WRAPPER>Wrapper for 'cargo add <name> --package <package>'
+    /// Wrapper for 'cargo add <name> --package <package>'
     #[command(name = "add:crate", visible_aliases=["crate"])]
     #[strum(serialize = "add:crate")]
+    #[strum(props(category = "wrappers"))]
     AddCrate {
         /// The name of the package to add
         name: String,
         /// The name of the target package in the workspace
+        #[arg(add = ArgValueCandidates::new(completions::workspace_packages))]
         package: String,
-        /// Pass in arguments for 'cargo add <name> --package <package>'
-        #[arg(long, allow_hyphen_values = true, num_args = 0..)]
-        args: Vec<String>,
+        #[command(flatten)]
+        args: PassThrough,
     },
-    /// <WRAPPER>Wrapper for 'cargo sqlx prepare --workspace'.
+    /// Wrapper for 'cargo sqlx prepare --workspace'.
     #[command(name = "prepare")]
     #[strum(serialize = "prepare")]
+    #[strum(props(category = "wrappers"))]
     Prepare {
-        /// Pass in arguments for 'cargo sqlx prepare --workspace'
-        #[arg(long, allow_hyphen_values = true, num_args = 0..)]
-        args: Vec<String>,
+        #[command(flatten)]
+        args: PassThrough,
     },
-    /// <WRAPPER>Wrapper for 'bun x vite preview'.
+    /// Wrapper for 'vite preview' through the package manager.
     #[command(name = "preview")]
     #[strum(serialize = "preview")]
+    #[strum(props(category = "wrappers"))]
     Preview {
-        /// Pass in arguments for 'bun x vite preview'
-        #[arg(long, allow_hyphen_values = true, num_args = 0..)]
-        args: Vec<String>,
+        #[command(flatten)]
+        args: PassThrough,
     },
-    /// <WRAPPER>Wrapper for 'bun x vitest'
+    /// Wrapper for 'vitest' through the package manager
     #[command(name = "test:unit", visible_aliases=["vitest"])]
     #[strum(serialize = "test:unit")]
+    #[strum(props(category = "wrappers"))]
     TestUnit {
-        /// Pass in arguments for 'bun x vitest'
-        #[arg(long, allow_hyphen_values = true, num_args = 0..)]
-        args: Vec<String>,
+        #[command(flatten)]
+        args: PassThrough,
     },
-    /// <WRAPPER>Wrapper for 'cargo sqlx migrate'.
+    /// Wrapper for 'cargo sqlx migrate'.
     #[command(name = "migrate")]
     #[strum(serialize = "migrate")]
+    #[strum(props(category = "wrappers"))]
     Migrate {
-        /// Pass in arguments for 'cargo sqlx migrate'
-        #[arg(long, allow_hyphen_values = true, num_args = 0..)]
-        args: Vec<String>,
+        #[command(flatten)]
+        args: PassThrough,
     },
 }
@@ -0,0 +1,363 @@
+use super::{
+    buffer_output, error, error_opt, garlic_exit, garlic_print, interrupted,
+    process::MAX_GROUPS,
+    run_log::{log_step, set_step},
+    take_output, CmdError,
+};
+use colored::Colorize;
+use std::{
+    num::NonZeroUsize,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Flags shared by the commands that run a [`Pipeline`]
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct PipelineArgs {
+    /// Keep running the next steps when a step fails
+    #[arg(long)]
+    pub continue_on_error: bool,
+    /// Skip the steps before this one
+    #[arg(long, value_name = "STEP")]
+    pub from: Option<String>,
+    /// Only run this step, can be repeated
+    #[arg(long, value_name = "STEP")]
+    pub only: Vec<String>,
+    /// How many steps can run at the same time, defaults to the number of CPUs
+    #[arg(long, short)]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+type StepFn<'a> = Box<dyn FnOnce() -> Result<(), CmdError> + Send + 'a>;
+
+struct Step<'a> {
+    name: &'static str,
+    /// The steps that have to finish before this one starts
+    after: Vec<&'static str>,
+    run: StepFn<'a>,
+}
+
+#[derive(Debug)]
+pub enum StepStatus {
+    Passed(Duration),
+    Failed(Duration, CmdError),
+    /// Left out by `--from` or `--only`
+    Skipped,
+    /// An earlier step failed
+    NotRun,
+}
+
+/// A sequence of named steps, such as the commands of `garlic spec`,
+/// that ends with a summary of how each step went.
+///
+/// Steps that don't depend on each other run at the same time. Their output is
+/// collected and printed at once when the step finishes.
+#[must_use]
+pub struct Pipeline<'a> {
+    steps: Vec<Step<'a>>,
+    args: PipelineArgs,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new(args: PipelineArgs) -> Self {
+        Pipeline {
+            steps: Vec::new(),
+            args,
+        }
+    }
+
+    /// Adds a step that starts after the previous one finished
+    pub fn step(
+        self,
+        name: &'static str,
+        run: impl FnOnce() -> Result<(), CmdError> + Send + 'a,
+    ) -> Self {
+        let after: Vec<_> = self
+            .steps
+            .last()
+            .map(|step| step.name)
+            .into_iter()
+            .collect();
+        self.step_after(name, &after, run)
+    }
+
+    /// Adds a step that starts once the steps in `after` finished, `&[]` lets it start right away
+    pub fn step_after(
+        mut self,
+        name: &'static str,
+        after: &[&'static str],
+        run: impl FnOnce() -> Result<(), CmdError> + Send + 'a,
+    ) -> Self {
+        for earlier in after {
+            assert!(
+                self.steps.iter().any(|step| step.name == *earlier),
+                "Expected step {earlier} to be added before {name}"
+            );
+        }
+
+        self.steps.push(Step {
+            name,
+            after: after.to_vec(),
+            run: Box::new(run),
+        });
+        self
+    }
+
+    /// Runs the steps, prints the summary and exits when a step failed
+    pub fn run(self) {
+        let start = Instant::now();
+        let statuses = self.run_steps();
+        print_summary(&statuses, start.elapsed());
+
+        let failure = statuses.iter().find_map(|(_, status)| match status {
+            StepStatus::Failed(_, e) => Some(e.exit_code()),
+            _ => None,
+        });
+
+        if let Some(code) = failure {
+            garlic_exit(code);
+        }
+    }
+
+    /// Runs the steps and returns the status of each of them
+    pub fn run_steps(self) -> Vec<(&'static str, StepStatus)> {
+        self.validate();
+
+        let selected = self.selected();
+        let jobs = self.jobs();
+        let continue_on_error = self.args.continue_on_error;
+        let total = selected.iter().filter(|selected| **selected).count();
+
+        let names: Vec<_> = self.steps.iter().map(|step| step.name).collect();
+        let after: Vec<Vec<usize>> = self
+            .steps
+            .iter()
+            .map(|step| {
+                step.after
+                    .iter()
+                    .filter_map(|name| names.iter().position(|other| other == name))
+                    .collect()
+            })
+            .collect();
+        let mut pending: Vec<_> = self.steps.into_iter().map(|step| Some(step.run)).collect();
+        let mut statuses: Vec<_> = selected
+            .iter()
+            .map(|selected| (!selected).then_some(StepStatus::Skipped))
+            .collect();
+
+        let mut number = 0;
+        let mut running = 0;
+        let mut failed = false;
+        let (sender, receiver) = mpsc::channel();
+
+        thread::scope(|scope| loop {
+            let waiting: Vec<_> = (0..names.len())
+                .filter(|index| pending[*index].is_some() && statuses[*index].is_none())
+                .collect();
+
+            if (failed && !continue_on_error) || interrupted() {
+                for index in waiting {
+                    statuses[index] = Some(StepStatus::NotRun);
+                }
+            } else {
+                let ready: Vec<_> = waiting
+                    .into_iter()
+                    .filter(|index| after[*index].iter().all(|dep| statuses[*dep].is_some()))
+                    .collect();
+
+                // A step that can't run next to others keeps its output live
+                if running == 0 && !ready.is_empty() && (ready.len() == 1 || jobs == 1) {
+                    let index = ready[0];
+                    let run = pending[index].take().expect("Expected a pending step");
+
+                    number += 1;
+                    print_header(number, total, names[index]);
+
+                    // Its commands own the terminal, so only how they went is logged
+                    set_step(Some(names[index]));
+                    let start = Instant::now();
+                    let result = run();
+                    let duration = start.elapsed();
+                    set_step(None);
+
+                    let status = finish(names[index], result, duration, None, &mut failed);
+                    statuses[index] = Some(status);
+                    continue;
+                }
+
+                for index in ready.into_iter().take(jobs.saturating_sub(running)) {
+                    let run = pending[index].take().expect("Expected a pending step");
+                    let sender = sender.clone();
+
+                    garlic_print(format!("Started {}", names[index].blue()));
+                    running += 1;
+                    let name = names[index];
+                    scope.spawn(move || {
+                        set_step(Some(name));
+                        buffer_output();
+                        let start = Instant::now();
+                        let result = run();
+                        let _ = sender.send((index, result, start.elapsed(), take_output()));
+                    });
+                }
+            }
+
+            if running == 0 {
+                break;
+            }
+
+            let (index, result, duration, output) = receiver
+                .recv()
+                .expect("Expected a running step to report back");
+            running -= 1;
+
+            number += 1;
+            print_header(number, total, names[index]);
+            print!("{output}");
+            statuses[index] = Some(finish(
+                names[index],
+                result,
+                duration,
+                Some(&output),
+                &mut failed,
+            ));
+        });
+
+        let statuses: Vec<_> = names
+            .into_iter()
+            .zip(statuses)
+            .map(|(name, status)| (name, status.unwrap_or(StepStatus::NotRun)))
+            .collect();
+
+        for (name, status) in &statuses {
+            match status {
+                StepStatus::Skipped => log_step(name, "skipped", None, None),
+                StepStatus::NotRun => log_step(name, "not run", None, None),
+                _ => {}
+            }
+        }
+
+        statuses
+    }
+
+    fn jobs(&self) -> usize {
+        let jobs = match self.args.jobs {
+            Some(jobs) => jobs,
+            None => thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+        };
+
+        jobs.get().min(MAX_GROUPS)
+    }
+
+    fn validate(&self) {
+        let names: Vec<_> = self.steps.iter().map(|step| step.name).collect();
+        for name in self.args.from.iter().chain(&self.args.only) {
+            if !names.contains(&name.as_str()) {
+                error(
+                    "unknown_step",
+                    format!("There is no step '{name}'. Steps: {}", names.join(", ")),
+                );
+            }
+        }
+    }
+
+    /// Which steps to run according to `--from` and `--only`
+    fn selected(&self) -> Vec<bool> {
+        let from = self
+            .args
+            .from
+            .as_ref()
+            .and_then(|from| self.steps.iter().position(|step| step.name == from))
+            .unwrap_or(0);
+
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                index >= from
+                    && (self.args.only.is_empty()
+                        || self.args.only.iter().any(|only| only == step.name))
+            })
+            .collect()
+    }
+}
+
+fn print_header(number: usize, total: usize, name: &str) {
+    println!();
+    println!(
+        "{} {}",
+        format!("[{number}/{total}]").bold(),
+        name.blue().bold()
+    );
+}
+
+/// Reports and logs how a step went, keeping the output of a failed step in the log
+fn finish(
+    name: &str,
+    result: Result<(), CmdError>,
+    duration: Duration,
+    output: Option<&str>,
+    failed: &mut bool,
+) -> StepStatus {
+    match result {
+        Ok(()) => {
+            log_step(name, "passed", Some(duration), None);
+            StepStatus::Passed(duration)
+        }
+        Err(e) => {
+            error_opt(e.kind(), &e);
+            log_step(name, "failed", Some(duration), output);
+            *failed = true;
+            StepStatus::Failed(duration, e)
+        }
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+    if seconds < 60.0 {
+        format!("{seconds:.1}s")
+    } else {
+        format!(
+            "{}m {:02}s",
+            duration.as_secs() / 60,
+            duration.as_secs() % 60
+        )
+    }
+}
+
+fn print_summary(statuses: &[(&'static str, StepStatus)], total: Duration) {
+    let width = statuses
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max("Step".len());
+
+    println!();
+    println!(
+        "{}",
+        format!("{:width$}  {:8}  Time", "Step", "Status")
+            .bold()
+            .underline()
+    );
+
+    for (name, status) in statuses {
+        let (label, time) = match status {
+            StepStatus::Passed(time) => ("passed".green(), Some(time)),
+            StepStatus::Failed(time, _) => ("failed".red(), Some(time)),
+            StepStatus::Skipped => ("skipped".dimmed(), None),
+            StepStatus::NotRun => ("not run".yellow(), None),
+        };
+        println!(
+            "{:width$}  {:8}  {}",
+            name.blue(),
+            label,
+            time.map(|time| format_duration(*time))
+                .unwrap_or("-".to_owned())
+        );
+    }
+
+    println!("{:width$}  {:8}  {}", "", "", format_duration(total).bold());
+}
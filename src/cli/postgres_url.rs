@@ -21,7 +21,7 @@ impl PostgresUrl {
 
         PostgresUrl {
             username: decode(username.unwrap_or(&username_pass)),
-            password: password.map(|pwd| decode(pwd)),
+            password: password.map(decode),
             host: parse_until(&mut chars, ":"),
             port: parse_until(&mut chars, "/")
                 .parse()
@@ -32,7 +32,7 @@ impl PostgresUrl {
 
     pub fn to_env_connection_string(&self) -> String {
         let uname = match &self.password {
-            Some(pwd) if !pwd.is_empty() => format!("{}:{}", encode(&self.username), encode(&pwd)),
+            Some(pwd) if !pwd.is_empty() => format!("{}:{}", encode(&self.username), encode(pwd)),
             _ => encode(&self.username),
         };
 
@@ -45,7 +45,7 @@ impl PostgresUrl {
     pub fn to_connection_params(&self) -> String {
         let uname = match &self.password {
             Some(pwd) if !pwd.is_empty() => {
-                format!("{} password={}", encode(&self.username), encode(&pwd))
+                format!("{} password={}", encode(&self.username), encode(pwd))
             }
             _ => encode(&self.username),
         };
@@ -53,6 +53,19 @@ impl PostgresUrl {
         format!("host={} port={} user={uname}", self.host, self.port)
     }
 
+    pub fn to_database_connection_params(&self) -> String {
+        let database = self.database.replace('\\', "\\\\").replace('\'', "\\'");
+        format!("{} dbname='{database}'", self.to_connection_params())
+    }
+
+    /// The same server, but pointing at another database.
+    pub fn with_database(&self, database: impl Into<String>) -> PostgresUrl {
+        PostgresUrl {
+            database: database.into(),
+            ..self.clone()
+        }
+    }
+
     pub fn is_running(&self) -> bool {
         match TcpStream::connect((&*self.host, self.port)) {
             Ok(stream) => {
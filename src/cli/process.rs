@@ -0,0 +1,280 @@
+use std::{
+    fmt::Display,
+    process::{Child, Command as StdCommand, ExitStatus},
+    sync::{
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Once,
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// How many commands can run at the same time and still receive Ctrl-C
+pub const MAX_GROUPS: usize = 32;
+
+/// The process groups of the commands that are running right now, 0 for a free slot
+static CHILD_GROUPS: [AtomicI32; MAX_GROUPS] = [const { AtomicI32::new(0) }; MAX_GROUPS];
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static DEFER_INTERRUPTS: AtomicBool = AtomicBool::new(false);
+static INSTALL: Once = Once::new();
+
+/// How long a process group gets to stop after being asked to, before it's killed
+const GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+/// Why garlic stopped a command, instead of it exiting on its own
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stopped {
+    TimedOut(Duration),
+    Interrupted,
+}
+
+impl Display for Stopped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stopped::TimedOut(timeout) => write!(f, "timed out after {timeout:?}"),
+            Stopped::Interrupted => write!(f, "was interrupted"),
+        }
+    }
+}
+
+/// Whether garlic or one of its commands received Ctrl-C or a termination signal
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Keeps garlic alive when it is interrupted while no command is running,
+/// so it can clean up first. Check [`interrupted`] to stop afterwards.
+pub fn defer_interrupts() {
+    install();
+    DEFER_INTERRUPTS.store(true, Ordering::SeqCst);
+}
+
+/// A running command, see [`started`]
+pub struct ChildGroup {
+    id: i32,
+    foreground: bool,
+    slot: Option<usize>,
+}
+
+/// Makes `command` start in its own process group, so its children can be stopped with it.
+/// With `foreground` it takes over the terminal, so Ctrl-C goes to the command directly.
+/// Returns whether the command will own the terminal, which is passed on to [`started`].
+pub fn prepare(command: &mut StdCommand, foreground: bool) -> bool {
+    install();
+    let foreground = foreground && imp::owns_terminal();
+    imp::prepare(command, foreground);
+    foreground
+}
+
+pub fn started(child: &Child, foreground: bool) -> ChildGroup {
+    let id = child.id() as i32;
+    let slot = CHILD_GROUPS.iter().position(|slot| {
+        slot.compare_exchange(0, id, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    });
+    let group = ChildGroup {
+        id,
+        foreground,
+        slot,
+    };
+
+    if group.foreground {
+        imp::give_terminal(group.id);
+    }
+
+    group
+}
+
+impl ChildGroup {
+    /// Asks the whole group to stop and kills it if it doesn't in time
+    pub fn terminate(&self, child: &mut Child) {
+        if !cfg!(unix) {
+            let _ = child.kill();
+            return;
+        }
+
+        imp::signal_group(self.id, imp::TERMINATE);
+
+        let deadline = Instant::now() + GRACE_PERIOD;
+        while Instant::now() < deadline {
+            if child.try_wait().is_ok_and(|status| status.is_some()) {
+                return;
+            }
+            sleep(Duration::from_millis(20));
+        }
+
+        imp::signal_group(self.id, imp::KILL);
+        let _ = child.kill();
+    }
+
+    /// Whether the command was stopped by Ctrl-C or a termination signal
+    pub fn interrupted_by(&self, status: &ExitStatus) -> bool {
+        let interrupted = imp::is_interrupt(status);
+        if interrupted {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+        }
+        interrupted || self::interrupted()
+    }
+}
+
+impl Drop for ChildGroup {
+    /// Takes the terminal back and stops grandchildren that outlived the command, like the
+    /// esbuild service of vite or the watcher of cargo-watch.
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            CHILD_GROUPS[slot].store(0, Ordering::SeqCst);
+        }
+        if self.foreground {
+            imp::take_terminal_back();
+        }
+
+        if !imp::group_alive(self.id) {
+            return;
+        }
+
+        imp::signal_group(self.id, imp::TERMINATE);
+        let deadline = Instant::now() + GRACE_PERIOD;
+        while imp::group_alive(self.id) {
+            if Instant::now() > deadline {
+                imp::signal_group(self.id, imp::KILL);
+                break;
+            }
+            sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+fn install() {
+    INSTALL.call_once(imp::install);
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::{CHILD_GROUPS, DEFER_INTERRUPTS, INTERRUPTED};
+    use libc::{c_int, SIGHUP, SIGINT, SIGTERM, SIGTTOU, SIG_DFL, SIG_IGN, STDIN_FILENO};
+    use std::{
+        os::unix::process::{CommandExt, ExitStatusExt},
+        process::{Command as StdCommand, ExitStatus},
+        sync::atomic::Ordering,
+    };
+
+    pub const TERMINATE: c_int = SIGTERM;
+    pub const KILL: c_int = libc::SIGKILL;
+
+    /// Runs inside the signal handler, so only async-signal-safe calls are allowed
+    extern "C" fn forward(signal: c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+
+        let mut forwarded = false;
+        for slot in &CHILD_GROUPS {
+            let group = slot.load(Ordering::SeqCst);
+            if group > 0 {
+                unsafe { libc::kill(-group, signal) };
+                forwarded = true;
+            }
+        }
+
+        if !forwarded && !DEFER_INTERRUPTS.load(Ordering::SeqCst) {
+            unsafe {
+                libc::signal(signal, SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+    }
+
+    pub fn install() {
+        let handler = forward as extern "C" fn(c_int) as libc::sighandler_t;
+        unsafe {
+            libc::signal(SIGINT, handler);
+            libc::signal(SIGTERM, handler);
+            libc::signal(SIGHUP, handler);
+            // Lets garlic take the terminal back from a command group that owned it
+            libc::signal(SIGTTOU, SIG_IGN);
+        }
+    }
+
+    pub fn prepare(command: &mut StdCommand, foreground: bool) {
+        unsafe {
+            command.pre_exec(move || {
+                libc::setpgid(0, 0);
+                if foreground {
+                    libc::tcsetpgrp(STDIN_FILENO, libc::getpid());
+                }
+                // Ignored signals stay ignored after exec
+                libc::signal(SIGTTOU, SIG_DFL);
+                Ok(())
+            });
+        }
+    }
+
+    pub fn owns_terminal() -> bool {
+        unsafe {
+            libc::isatty(STDIN_FILENO) == 1 && libc::tcgetpgrp(STDIN_FILENO) == libc::getpgrp()
+        }
+    }
+
+    pub fn give_terminal(group: i32) {
+        unsafe { libc::tcsetpgrp(STDIN_FILENO, group) };
+    }
+
+    pub fn take_terminal_back() {
+        unsafe { libc::tcsetpgrp(STDIN_FILENO, libc::getpgrp()) };
+    }
+
+    pub fn signal_group(group: i32, signal: c_int) {
+        unsafe { libc::kill(-group, signal) };
+    }
+
+    pub fn group_alive(group: i32) -> bool {
+        unsafe { libc::kill(-group, 0) == 0 }
+    }
+
+    pub fn is_interrupt(status: &ExitStatus) -> bool {
+        matches!(status.signal(), Some(SIGINT | SIGTERM | SIGHUP))
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use super::{super::garlic_exit, CHILD_GROUPS, DEFER_INTERRUPTS, INTERRUPTED};
+    use std::{
+        process::{Command as StdCommand, ExitStatus},
+        sync::atomic::Ordering,
+    };
+
+    pub const TERMINATE: i32 = 0;
+    pub const KILL: i32 = 0;
+
+    /// The console sends Ctrl-C to every attached process, so the command receives it as well
+    pub fn install() {
+        let _ = ctrlc::set_handler(|| {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+            let running = CHILD_GROUPS
+                .iter()
+                .any(|slot| slot.load(Ordering::SeqCst) != 0);
+            if !running && !DEFER_INTERRUPTS.load(Ordering::SeqCst) {
+                garlic_exit(130);
+            }
+        });
+    }
+
+    pub fn prepare(_command: &mut StdCommand, _foreground: bool) {}
+
+    pub fn owns_terminal() -> bool {
+        false
+    }
+
+    pub fn give_terminal(_group: i32) {}
+
+    pub fn take_terminal_back() {}
+
+    pub fn signal_group(_group: i32, _signal: i32) {}
+
+    pub fn group_alive(_group: i32) -> bool {
+        false
+    }
+
+    pub fn is_interrupt(_status: &ExitStatus) -> bool {
+        false
+    }
+}
@@ -0,0 +1,233 @@
+use super::{dotgarlic_path, error, Cmd, Manifest, PackageManager};
+use serde::Deserialize;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    manifest_path: PathBuf,
+    dependencies: Vec<Dependency>,
+}
+
+#[derive(Deserialize)]
+struct Dependency {
+    /// Only set for path dependencies
+    path: Option<PathBuf>,
+}
+
+/// The garlic project a command works on, found through its `.garlic` anchor
+#[derive(Clone, Debug, PartialEq)]
+pub struct Project {
+    pub root: PathBuf,
+    /// The SvelteKit frontend
+    pub app: PathBuf,
+    /// The crate that is compiled to wasm for the frontend
+    pub export_fns: PathBuf,
+    /// From the manifest or `--pm`
+    pub package_manager: PackageManager,
+}
+
+impl Project {
+    /// The project at `root`, exits when its manifest is invalid
+    pub fn at(root: impl Into<PathBuf>) -> Self {
+        Project::try_at(root).unwrap_or_else(|e| error("invalid_manifest", e))
+    }
+
+    /// Like [`Project::at`], but returns why the manifest is invalid
+    pub fn try_at(root: impl Into<PathBuf>) -> Result<Self, String> {
+        let root = root.into();
+        Ok(Project {
+            app: root.join("app"),
+            package_manager: Manifest::try_load(&root)?
+                .package_manager
+                .unwrap_or_default(),
+            export_fns: root.join("export_fns"),
+            root,
+        })
+    }
+
+    /// The project that contains `start`, or the current directory without it.
+    /// `None` when `start` doesn't exist, its parents are never searched then
+    pub fn find(start: Option<&Path>) -> Option<Self> {
+        Project::find_root(start).map(Project::at)
+    }
+
+    /// Like [`Project::find`], but `None` instead of exiting when the manifest is invalid
+    pub fn try_find(start: Option<&Path>) -> Option<Self> {
+        Project::find_root(start).and_then(|root| Project::try_at(root).ok())
+    }
+
+    fn find_root(start: Option<&Path>) -> Option<PathBuf> {
+        let current_dir = env::current_dir().ok()?;
+        let mut dir = match start {
+            Some(start) => current_dir.join(start),
+            None => current_dir,
+        };
+        if !dir.is_dir() {
+            return None;
+        }
+
+        loop {
+            if dir.join(".garlic").exists() {
+                return Some(dir);
+            }
+
+            if !dir.pop() {
+                return None;
+            }
+        }
+    }
+
+    /// The value of `-C`/`--project` in `args`, for when they couldn't be parsed completely,
+    /// like while completing or showing help
+    pub fn path_arg(args: impl IntoIterator<Item = String>) -> Option<PathBuf> {
+        let mut args = args.into_iter();
+        let mut path = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-C" | "--project" => path = args.next().map(PathBuf::from),
+                _ => {
+                    if let Some(value) = arg
+                        .strip_prefix("--project=")
+                        .or_else(|| arg.strip_prefix("-C"))
+                        .filter(|value| !value.is_empty())
+                    {
+                        path = Some(PathBuf::from(value));
+                    }
+                }
+            }
+        }
+        path
+    }
+
+    /// Like [`Project::find`], but exits when there is no project
+    pub fn require(start: Option<&Path>) -> Self {
+        Project::find(start).unwrap_or_else(|| match start {
+            Some(start) if !start.is_dir() => error(
+                "no_project_dir",
+                format!("{} doesn't exist or isn't a directory", start.display()),
+            ),
+            Some(start) => error(
+                "no_dotgarlic",
+                format!("No .garlic found in {} or its parents", start.display()),
+            ),
+            None => error(
+                "no_dotgarlic",
+                ".garlic File not found. This file is used as an anchor for projects so you can run garlic commands in subdirectories. Use --project to point garlic at a project elsewhere.",
+            ),
+        })
+    }
+
+    /// Uses `package_manager` instead of the one in the manifest, if set
+    pub fn with_package_manager(mut self, package_manager: Option<PackageManager>) -> Self {
+        if let Some(package_manager) = package_manager {
+            self.package_manager = package_manager;
+        }
+        self
+    }
+
+    /// `name` inside `.garlic`, see [`dotgarlic_path`]
+    pub fn dotgarlic(&self, name: impl AsRef<Path>) -> PathBuf {
+        dotgarlic_path(&self.root, name)
+    }
+
+    /// The names of the cargo workspace packages, empty when cargo can't read them
+    pub fn workspace_packages(&self) -> Vec<String> {
+        self.metadata()
+            .map(|metadata| {
+                metadata
+                    .packages
+                    .into_iter()
+                    .map(|package| package.name)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The directories of the local crates that the crate in `dir` depends on, directly or
+    /// through other local crates. Empty when cargo can't read them
+    pub fn path_dependencies(&self, dir: &Path) -> Vec<PathBuf> {
+        let Some(metadata) = self.metadata() else {
+            return Vec::new();
+        };
+
+        let package_dir = |package: &Package| package.manifest_path.parent().map(Path::to_owned);
+        let same_dir = |a: &Path, b: &Path| match (a.canonicalize(), b.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        };
+
+        let mut found: Vec<PathBuf> = Vec::new();
+        let mut pending = vec![dir.to_owned()];
+        while let Some(dir) = pending.pop() {
+            let Some(package) = metadata
+                .packages
+                .iter()
+                .find(|package| package_dir(package).is_some_and(|other| same_dir(&other, &dir)))
+            else {
+                continue;
+            };
+
+            for path in package
+                .dependencies
+                .iter()
+                .filter_map(|dep| dep.path.as_ref())
+            {
+                if !found.iter().any(|other| same_dir(other, path)) {
+                    found.push(path.clone());
+                    pending.push(path.clone());
+                }
+            }
+        }
+
+        found
+    }
+
+    fn metadata(&self) -> Option<Metadata> {
+        let output = self
+            .cmd("cargo metadata --no-deps --format-version 1 --offline")
+            .read_only()
+            .capture()
+            .ok()?;
+
+        serde_json::from_str(&output.stdout).ok()
+    }
+
+    /// Runs `command` in the project root
+    pub fn cmd(&self, command: impl AsRef<str>) -> Cmd {
+        Cmd::run(command).dir(&self.root)
+    }
+
+    /// Installs the frontend dependencies
+    pub fn install(&self) -> Cmd {
+        Cmd::run(self.package_manager.install()).dir(&self.app)
+    }
+
+    /// Runs a binary of the frontend dependencies, like `vite dev`, in the frontend
+    pub fn exec(&self, command: impl AsRef<str>) -> Cmd {
+        Cmd::run(format!(
+            "{} {}",
+            self.package_manager.exec(),
+            command.as_ref()
+        ))
+        .dir(&self.app)
+    }
+
+    /// Like [`Project::exec`], for tools that may not be a dependency of the frontend
+    pub fn dlx(&self, command: impl AsRef<str>) -> Cmd {
+        Cmd::run(format!(
+            "{} {}",
+            self.package_manager.dlx(),
+            command.as_ref()
+        ))
+        .dir(&self.app)
+    }
+}
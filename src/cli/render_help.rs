@@ -1,406 +1,715 @@
-use parser::*;
-use std::{env, process::exit};
-
-use clap::CommandFactory;
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
+
+use clap::{
+    error::{ContextKind, ContextValue, ErrorKind},
+    Arg, Command, CommandFactory, Parser,
+};
 use colored::Colorize;
+use strum::{Display, EnumIter, EnumProperty, EnumString, IntoEnumIterator};
 
-use super::{GarlicCommand, GarlicParser};
-
-pub fn print_info() {
-    println!(
-        "{} v{}",
-        env!("CARGO_PKG_NAME").bold().green(),
-        env!("CARGO_PKG_VERSION")
-    );
-
-    println!();
-    println!("{}", "--PACKAGE--".blue());
-    println!("Authors: {}", env!("CARGO_PKG_AUTHORS").blue());
-    println!("License: {}", env!("CARGO_PKG_LICENSE").blue());
-    println!("Description: {}", env!("CARGO_PKG_DESCRIPTION").blue());
-    println!("Repository: {}", env!("CARGO_PKG_REPOSITORY").blue());
-
-    println!();
-    println!("{}", "--BUILD--".blue());
-    println!(
-        "Location: {}",
-        env::current_exe()
-            .unwrap_or_default()
-            .to_str()
-            .unwrap_or_default()
-            .blue()
-    );
-    println!(
-        "Profile: {}",
-        if cfg!(debug_assertions) {
-            "debug".blue()
-        } else {
-            "release".blue()
-        }
-    );
-    println!("Target architecture: {}", std::env::consts::ARCH.blue());
-    println!("Target OS: {}", std::env::consts::OS.blue());
+use super::{error_opt, garlic_exit, GarlicCommand, GarlicParser, Project};
+
+const BIN: &str = env!("CARGO_BIN_NAME");
+
+/// Help is wrapped to the terminal, but never wider than this
+const MAX_WIDTH: usize = 100;
+
+/// Names longer than this get their description on the next line
+const MAX_NAME_WIDTH: usize = 28;
+
+/// A command as the help and the reference docs show it
+pub struct CommandDoc {
+    pub name: String,
+    pub description: String,
+    pub usage: String,
+    pub arguments: Vec<Entry>,
+    /// The global options, only listed in the help of the command itself
+    pub global_options: Vec<Entry>,
+    pub aliases: Vec<String>,
+    pub category: Category,
 }
 
-impl GarlicParser {
-    pub fn render_help(command: Option<GarlicCommand>) -> ! {
-        match command {
-            Some(command) => {
-                let mut app = GarlicParser::command();
-                let command_name = command.to_string();
-                let subcmd = app
-                    .find_subcommand_mut(&command_name)
-                    .expect("Expected subcommand");
+/// An argument or option with its description
+pub struct Entry {
+    pub name: String,
+    pub description: String,
+    /// Options are listed apart from the positional arguments
+    pub flag: bool,
+}
 
-                let help = subcmd.render_help().to_string();
-                let (name, details) = help.split_once("\n").unzip();
+/// The sections of the help, declared per command with `#[strum(props(category = "..."))]`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display, EnumIter, EnumString, clap::ValueEnum)]
+pub enum Category {
+    #[strum(to_string = "Project", serialize = "project")]
+    Project,
+    #[strum(to_string = "Database", serialize = "database")]
+    Database,
+    #[strum(to_string = "Codegen", serialize = "codegen")]
+    Codegen,
+    #[strum(to_string = "Testing", serialize = "testing")]
+    Testing,
+    #[strum(to_string = "Build & Release", serialize = "build")]
+    Build,
+    /// Commands that run another tool, like `cargo run`
+    #[strum(to_string = "Wrappers", serialize = "wrappers")]
+    Wrappers,
+}
 
-                println!(
-                    "{} v{}: {}",
-                    env!("CARGO_PKG_NAME").bold().green(),
-                    env!("CARGO_PKG_VERSION"),
-                    command_name.blue()
-                );
+impl GarlicCommand {
+    /// `None` only when the variant forgot its category, which a test catches
+    pub fn category(&self) -> Option<Category> {
+        self.get_str("category")
+            .and_then(|category| category.parse().ok())
+    }
+}
 
-                let name = name.unwrap();
-                parse_command(&name.replace("<WRAPPER>", ""), true);
+/// The clap model of garlic, built so that global options are part of every command
+pub fn garlic_command() -> Command {
+    let mut cli = GarlicParser::command().bin_name(BIN);
+    cli.build();
+    cli
+}
 
-                println!();
+/// Every visible command of `cli`, in the order they are declared
+pub fn command_docs(cli: &Command) -> Vec<CommandDoc> {
+    cli.get_subcommands()
+        .filter(|command| !command.is_hide_set())
+        .map(command_doc)
+        .collect()
+}
 
-                let details = details
-                    .unwrap()
-                    .replace("Usage:", &format!("Usage: {}", env!("CARGO_BIN_NAME")));
+pub fn command_doc(command: &Command) -> CommandDoc {
+    let name = command.get_name().to_owned();
+    let (global_options, arguments) = command
+        .get_arguments()
+        .partition::<Vec<_>, _>(|arg| arg.is_global_set());
+
+    CommandDoc {
+        usage: usage(command),
+        description: command
+            .get_about()
+            .map(|about| about.to_string())
+            .unwrap_or_default(),
+        arguments: arguments.into_iter().filter_map(argument).collect(),
+        global_options: global_options.into_iter().filter_map(argument).collect(),
+        aliases: command
+            .get_visible_aliases()
+            .map(|alias| alias.to_owned())
+            .collect(),
+        category: GarlicCommand::iter()
+            .find(|variant| variant.to_string() == name)
+            .and_then(|variant| variant.category())
+            .unwrap_or_else(|| panic!("Expected {name} to have a category")),
+        name,
+    }
+}
 
-                let mut parser = Parser::new(&details);
-                parse_lines(&mut parser);
+/// The first of `args` that names a command or one of its aliases
+fn typed_command(args: &[String]) -> Option<GarlicCommand> {
+    let cli = GarlicParser::command();
+    let name = args
+        .iter()
+        .find_map(|arg| cli.find_subcommand(arg))?
+        .get_name();
 
-                let aliases: Vec<_> = subcmd.get_visible_aliases().collect();
-                if aliases.len() > 0 {
-                    println!();
-                    print!("{} ", "Aliases:".bold().underline());
+    GarlicCommand::iter().find(|command| command.to_string() == name)
+}
 
-                    let mut aliases = aliases.iter();
-                    parse_command_alias(aliases.next().unwrap());
+/// What a wrapper runs in the project, with its arguments as placeholders.
+/// `None` when the project can't be loaded
+fn runs(command: &Command) -> Option<String> {
+    let args = env::args().skip(1).take_while(|arg| arg != "--");
+    let project = Project::try_find(Project::path_arg(args).as_deref())?;
+
+    let placeholders = command
+        .get_arguments()
+        .filter(|arg| arg.is_positional() && arg.is_required_set())
+        .map(value_name);
+    let line = [BIN.to_owned(), command.get_name().to_owned()]
+        .into_iter()
+        .chain(placeholders);
+
+    let parsed = GarlicParser::try_parse_from(line).ok()?;
+    let cmd = parsed.command?.wrapped_cmd(&project)?;
+    Some(format!("{} [ARGS]...", cmd.command_line()))
+}
 
-                    for alias in aliases {
-                        print!(", ");
-                        parse_command_alias(alias);
-                    }
-                    println!();
-                }
+/// `garlic <name> [OPTIONS] <REQUIRED> [OPTIONAL]...`
+fn usage(command: &Command) -> String {
+    let mut usage = format!("{BIN} {}", command.get_name());
+    let visible = || command.get_arguments().filter(|arg| !arg.is_hide_set());
 
-                exit(0);
-            }
-            None => {
-                let help = GarlicParser::command()
-                    .render_help()
-                    .to_string()
-                    .replace(env!("CARGO_PKG_NAME"), env!("CARGO_BIN_NAME"));
-
-                let (cli_description, details) = help.split_once("\n").unzip();
-
-                println!(
-                    "{} v{}",
-                    env!("CARGO_PKG_NAME").bold().green(),
-                    env!("CARGO_PKG_VERSION")
-                );
-                parse_command(cli_description.unwrap(), true);
-                println!();
-                let mut parser = Parser::new(details.unwrap());
-
-                parse_lines(&mut parser);
-                // GarlicParser::command().print_help().unwrap();
-                exit(0);
-            }
-        }
+    if visible().any(|arg| !arg.is_positional()) {
+        usage.push_str(" [OPTIONS]");
     }
-}
 
-fn parse_lines(parser: &mut Parser) {
-    let mut current = String::new();
-
-    while let Some(ch) = parser.next() {
-        match ch {
-            ':' if parser.peek().is_some_and(|p| p.is_whitespace()) => {
-                current.push(ch);
-                match &*current {
-                    "Commands:" => {
-                        print!("{}", current.bold().underline());
-                        current.clear();
-                        parse_commands(parser);
-                    }
-                    "Options:" => {
-                        print!("{}", current.bold().underline());
-                        current.clear();
-                        parse_options(parser);
-                    }
-                    "Arguments:" => {
-                        print!("{}", current.bold().underline());
-                        current.clear();
-                        parse_arguments(parser);
-                    }
-                    "Usage:" => {
-                        print!("{}", current.bold().underline());
-                        current.clear();
-                        parse_usage(parser);
-                    }
-                    _ => {}
-                }
-            }
-            '\n' => {
-                current.push(ch);
-                print!("{current}");
-                current.clear();
-            }
-            _ => current.push(ch),
+    for arg in visible().filter(|arg| arg.is_positional()) {
+        let value = value_name(arg);
+        if arg.is_last_set() {
+            usage.push_str(&format!(" [-- <{value}>...]"));
+            continue;
+        }
+
+        let many = arg.get_num_args().is_some_and(|num| num.max_values() > 1);
+        let dots = if many { "..." } else { "" };
+
+        match arg.is_required_set() {
+            true => usage.push_str(&format!(" <{value}>{dots}")),
+            false => usage.push_str(&format!(" [{value}]{dots}")),
         }
     }
 
-    print!("{current}")
+    usage
+}
+
+fn value_name(arg: &Arg) -> String {
+    arg.get_value_names()
+        .and_then(|names| names.first())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| arg.get_id().as_str().to_uppercase())
 }
 
-fn parse_arguments(parser: &mut Parser) {
-    let mut line = String::new();
-    print!("{}", parser.next().unwrap_or('\n'));
+pub fn argument(arg: &Arg) -> Option<Entry> {
+    if arg.is_hide_set() {
+        return None;
+    }
 
-    while let Some(ch) = parser.next() {
-        line.push(ch);
-        if ch == '\n' {
-            if line.chars().all(|c| c.is_whitespace()) {
-                break;
+    let value = value_name(arg);
+    let takes_value = arg.get_num_args().is_some_and(|num| num.takes_values());
+
+    let name = match (arg.get_short(), arg.get_long()) {
+        (None, None) if arg.is_last_set() => format!("-- <{value}>..."),
+        (None, None) => format!("<{value}>"),
+        (short, long) => {
+            let flags: Vec<_> = short
+                .map(|short| format!("-{short}"))
+                .into_iter()
+                .chain(long.map(|long| format!("--{long}")))
+                .collect();
+            match takes_value {
+                true => format!("{} <{value}>", flags.join(", ")),
+                false => flags.join(", "),
             }
-
-            parse_command(&line, false);
-            line.clear();
         }
+    };
+
+    let mut description = arg
+        .get_help()
+        .map(|help| help.to_string())
+        .unwrap_or_default();
+    let possible: Vec<_> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_owned())
+        .collect();
+    if takes_value && !possible.is_empty() {
+        description.push_str(&format!(" (one of {})", possible.join(", ")));
+    }
+    let defaults: Vec<_> = arg
+        .get_default_values()
+        .iter()
+        .map(|value| value.to_string_lossy())
+        .collect();
+    if takes_value && !defaults.is_empty() {
+        description.push_str(&format!(" (default {})", defaults.join(", ")));
     }
 
-    println!();
+    Some(Entry {
+        name,
+        description,
+        flag: !arg.is_positional(),
+    })
 }
 
-fn parse_commands(parser: &mut Parser) {
-    let mut commands = vec![];
+impl GarlicParser {
+    pub fn render_help(command: Option<GarlicCommand>) -> ! {
+        let cli = garlic_command();
+        let help = Help::new();
 
-    let mut line = String::new();
-    print!("{}", parser.next().unwrap_or('\n'));
+        let help = match command {
+            Some(command) => {
+                let subcommand = cli
+                    .find_subcommand(command.to_string())
+                    .expect("Expected subcommand");
+                help.command(&command_doc(subcommand), runs(subcommand))
+            }
+            None => help.overview(&cli),
+        };
+
+        print!("{help}");
+        garlic_exit(0)
+    }
 
-    while let Some(ch) = parser.next() {
-        line.push(ch);
-        if ch == '\n' {
-            if line.chars().all(|c| c.is_whitespace()) {
-                break;
+    /// Exits with clap's error, or with suggestions when the command is mistyped
+    pub fn parse_error(error: clap::Error) -> ! {
+        match error.get(ContextKind::InvalidSubcommand) {
+            Some(ContextValue::String(typed)) if error.kind() == ErrorKind::InvalidSubcommand => {
+                GarlicParser::unknown_command(typed)
+            }
+            // `--help` is a plain flag, so clap still wants the required arguments
+            _ if error.kind() == ErrorKind::MissingRequiredArgument => {
+                let args: Vec<_> = env::args().skip(1).take_while(|arg| arg != "--").collect();
+                if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+                    GarlicParser::render_help(typed_command(&args))
+                }
+                error.exit()
             }
-            commands.push(line.clone());
-            line.clear();
+            _ => error.exit(),
         }
     }
 
-    let (non_wrappers, wrappers) = (
-        commands.iter().filter(|comm| !comm.contains("<WRAPPER>")),
-        commands
-            .iter()
-            .filter(|comm| comm.contains("<WRAPPER>"))
-            .collect::<Vec<_>>(),
-    );
-
-    for command in non_wrappers {
-        parse_command(command, false);
-    }
+    fn unknown_command(typed: &str) -> ! {
+        plain_unless_terminal();
+        error_opt(
+            "unknown_command",
+            format!("'{typed}' isn't a garlic command"),
+        );
+
+        let suggestions = suggest_commands(typed);
+        if !suggestions.is_empty() {
+            println!();
+            println!("{}", "Did you mean:".bold().underline());
+        }
+        for suggestion in suggestions {
+            match suggestion.alias_of {
+                Some(command) => println!(
+                    "  {} (alias of {})",
+                    command_name(&suggestion.name),
+                    command_name(&command)
+                ),
+                None => println!("  {}", command_name(&suggestion.name)),
+            }
+        }
 
-    if !wrappers.is_empty() {
         println!();
-        println!("{}", "Wrapper commands:".bold().underline());
+        println!(
+            "Run {} to see every command",
+            format!("{BIN} help").bold().green()
+        );
+        garlic_exit(2)
+    }
 
-        for command in wrappers {
-            parse_command(&command.replace("<WRAPPER>", ""), false);
-        }
+    /// `garlic help <category>`
+    pub fn render_category(category: Category) -> ! {
+        print!("{}", Help::new().category(&garlic_command(), category));
+        garlic_exit(0)
     }
+}
 
-    print!("{line}");
+/// Turns colors off when `NO_COLOR` is set or stdout isn't a terminal
+fn plain_unless_terminal() {
+    if env::var_os("NO_COLOR").is_some() || !io::stdout().is_terminal() {
+        colored::control::set_override(false);
+    }
 }
 
-fn parse_command(command: &str, description_only: bool) {
-    let mut parser = Parser::new(command);
+fn terminal_width() -> usize {
+    console::Term::stdout()
+        .size_checked()
+        .map(|(_, columns)| columns as usize)
+        .unwrap_or(MAX_WIDTH)
+        .min(MAX_WIDTH)
+}
 
-    let mut current = String::new();
-    let mut whitespace = 0;
-    let mut is_string = false;
-    let mut aliases = false;
+/// Builds the colored help text, wrapped to `width`
+struct Help {
+    width: usize,
+    text: String,
+}
 
-    while let Some(ch) = parser.next() {
-        if ch == '\'' && !is_string {
-            print!("{current}");
-            current.clear();
-            is_string = true;
-            current.push(ch);
-            continue;
+impl Help {
+    /// Plain when `NO_COLOR` is set or stdout isn't a terminal
+    fn new() -> Self {
+        plain_unless_terminal();
+        Help {
+            width: terminal_width(),
+            text: String::new(),
         }
-        current.push(ch);
+    }
 
-        if is_string {
-            if ch == '\'' {
-                print!("{}", current.bold().cyan());
-                current.clear();
-                is_string = false;
-            }
-            continue;
+    fn overview(mut self, cli: &Command) -> String {
+        self.title(None);
+        if let Some(about) = cli.get_about() {
+            self.paragraph(&about.to_string());
         }
 
-        if current.contains("[aliases: ") {
-            print!("{current}");
-            current.clear();
-            aliases = true;
-            continue;
-        }
+        self.text.push('\n');
+        self.usage(&format!("{BIN} [OPTIONS] <COMMAND>"));
 
-        if aliases {
-            if current.ends_with(",") {
-                _ = current.pop();
-                parse_command_alias(&current);
-                print!(", ");
-                current.clear();
-                parser.advance();
-            } else if current.ends_with("]") {
-                _ = current.pop();
-                parse_command_alias(&current);
-                print!("]");
-                current.clear();
-                aliases = false;
-            }
-            continue;
-        }
+        let groups = group_by_category(command_docs(cli));
+        self.categories(&groups);
 
-        if !description_only
-            && ch.is_whitespace()
-            && parser.peek().is_some_and(|c| !c.is_whitespace())
-        {
-            whitespace += 1;
+        let options: Vec<_> = cli.get_arguments().filter_map(argument).collect();
+        self.entries("Options:", &options);
+        self.text
+    }
 
-            if whitespace == 2 {
-                print!("{}", current.blue());
-                current.clear();
-            }
+    /// Only the commands of `category`
+    fn category(mut self, cli: &Command, category: Category) -> String {
+        self.title(Some(&category.to_string()));
+
+        let groups: Vec<_> = group_by_category(command_docs(cli))
+            .into_iter()
+            .filter(|(group, _)| *group == category)
+            .collect();
+        self.categories(&groups);
+        self.text
+    }
+
+    /// A section per category, with the names of all of them aligned
+    fn categories(&mut self, groups: &[(Category, Vec<CommandDoc>)]) {
+        let name_width = name_width(
+            groups
+                .iter()
+                .flat_map(|(_, commands)| commands)
+                .map(|command| command.name.as_str()),
+        );
+
+        for (category, commands) in groups {
+            self.section(&format!("{category}:"));
+            let rows: Vec<_> = commands
+                .iter()
+                .map(|command| {
+                    let mut description = command.description.clone();
+                    if !command.aliases.is_empty() {
+                        description
+                            .push_str(&format!(" [aliases: {}]", command.aliases.join(", ")));
+                    }
+                    (command.name.clone(), description)
+                })
+                .collect();
+            self.rows(&rows, name_width, command_name);
         }
+    }
+
+    fn command(mut self, command: &CommandDoc, runs: Option<String>) -> String {
+        self.title(Some(&command.name));
+        self.paragraph(&command.description);
 
-        if !description_only && whitespace < 2 && parser.peek().is_some_and(|c| c == ':') {
-            print!("{}", current.blue());
-            current.clear();
-            parser.advance();
-            print!("{}", ":".bold());
+        self.text.push('\n');
+        self.usage(&command.usage);
+        if let Some(runs) = runs {
+            self.text
+                .push_str(&format!("{} {}\n", "Runs:".bold().underline(), runs.cyan()));
         }
 
-        if current.contains("[<ARGS>...]") {
-            print!("{}", current.split("[<ARGS>...]").next().unwrap_or(""));
-            print!("[{}]", "<ARGS>...".yellow());
-            current.clear();
-            continue;
+        let (arguments, options): (Vec<_>, Vec<_>) =
+            command.arguments.iter().partition(|entry| !entry.flag);
+        self.entries("Arguments:", arguments);
+
+        self.entries(
+            "Options:",
+            options.into_iter().chain(&command.global_options),
+        );
+
+        if !command.aliases.is_empty() {
+            let aliases: Vec<_> = command
+                .aliases
+                .iter()
+                .map(|alias| command_name(alias))
+                .collect();
+            self.text.push('\n');
+            self.text.push_str(&format!(
+                "{} {}\n",
+                "Aliases:".bold().underline(),
+                aliases.join(", ")
+            ));
         }
+
+        self.text
     }
 
-    print!("{current}");
-}
+    fn title(&mut self, command: Option<&str>) {
+        let title = format!(
+            "{} v{}",
+            env!("CARGO_PKG_NAME").bold().green(),
+            env!("CARGO_PKG_VERSION")
+        );
 
-fn parse_command_alias(alias: &str) {
-    let mut alias_split = alias.split(":");
+        match command {
+            Some(command) => self
+                .text
+                .push_str(&format!("{title}: {}\n", command.blue())),
+            None => self.text.push_str(&format!("{title}\n")),
+        }
+    }
 
-    print!("{}", alias_split.next().unwrap_or("").blue());
+    fn paragraph(&mut self, text: &str) {
+        for line in highlight(wrap(text, self.width)) {
+            self.text.push_str(&format!("{line}\n"));
+        }
+    }
 
-    for split in alias_split {
-        print!("{}{}", ":".bold(), split.blue());
+    fn section(&mut self, title: &str) {
+        self.text.push('\n');
+        self.text
+            .push_str(&format!("{}\n", title.bold().underline()));
     }
-}
 
-fn parse_options(parser: &mut Parser) {
-    let mut current = String::new();
-    let mut second_part = false;
+    fn usage(&mut self, usage: &str) {
+        let words: Vec<_> = usage
+            .split(' ')
+            .map(|word| match word {
+                BIN => word.bold().green().to_string(),
+                "[OPTIONS]" => format!("[{}]", "OPTIONS".green().bold()),
+                word if word.starts_with(['<', '[']) => word.blue().to_string(),
+                word => command_name(word),
+            })
+            .collect();
+
+        self.text.push_str(&format!(
+            "{} {}\n",
+            "Usage:".bold().underline(),
+            words.join(" ")
+        ));
+    }
 
-    while let Some(ch) = parser.next() {
-        current.push(ch);
-        if !second_part
-            && parser.peek().is_some_and(|p| p.is_whitespace())
-            && current.contains("--")
-        {
-            print!("{}", current.bold().green());
-            current.clear();
-            second_part = true;
+    fn entries<'e>(&mut self, title: &str, entries: impl IntoIterator<Item = &'e Entry>) {
+        let entries: Vec<_> = entries.into_iter().collect();
+        if entries.is_empty() {
+            return;
         }
 
-        if ch == '\n' {
-            parse_command(&current, true);
-            current.clear();
-            second_part = false;
+        self.section(title);
+        let rows: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.description.clone()))
+            .collect();
+        let name_width = name_width(rows.iter().map(|(name, _)| name.as_str()));
+        let is_flag = |name: &str| entries.iter().any(|entry| entry.flag && entry.name == name);
+
+        self.rows(&rows, name_width, |name| match is_flag(name) {
+            true => name.bold().green().to_string(),
+            false => name.blue().to_string(),
+        });
+    }
+
+    /// Two columns, the names styled with `style` and the descriptions wrapped next to them
+    fn rows(
+        &mut self,
+        rows: &[(String, String)],
+        name_width: usize,
+        style: impl Fn(&str) -> String,
+    ) {
+        let indent = name_width + 4;
+        let description_width = self.width.saturating_sub(indent).max(20);
+
+        for (name, description) in rows {
+            let lines = highlight(wrap(description, description_width));
+            let name_len = name.chars().count();
+            self.text.push_str(&format!("  {}", style(name)));
+
+            let mut lines = lines.into_iter();
+            if name_len > name_width {
+                self.text.push('\n');
+            } else if let Some(first) = lines.next() {
+                let padding = " ".repeat(indent - 2 - name_len);
+                self.text.push_str(&format!("{padding}{first}"));
+            }
+            self.text.push('\n');
+
+            for line in lines {
+                self.text
+                    .push_str(&format!("{}{line}\n", " ".repeat(indent)));
+            }
         }
     }
+}
 
-    parse_command(&current, true);
+/// The width of the name column, names longer than [`MAX_NAME_WIDTH`] don't count
+fn name_width<'n>(names: impl Iterator<Item = &'n str>) -> usize {
+    names
+        .map(|name| name.chars().count())
+        .filter(|width| *width <= MAX_NAME_WIDTH)
+        .max()
+        .unwrap_or_default()
 }
 
-fn parse_usage(parser: &mut Parser) {
-    let mut current = String::new();
+/// `db:seed` with the parts in blue and the colons in bold
+fn command_name(name: &str) -> String {
+    name.split(':')
+        .map(|part| part.blue().to_string())
+        .collect::<Vec<_>>()
+        .join(&":".bold().to_string())
+}
 
-    while let Some(ch) = parser.next() {
-        current.push(ch);
+/// Splits `text` into lines of at most `width` characters, only breaking between words
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
 
-        if ch == '\n' {
-            break;
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
         }
+        lines.push(line);
+    }
 
-        if ch == '[' {
-            print!("{current}");
-            current.clear();
+    lines
+}
 
-            while let Some(ch) = parser.next() {
-                if ch == ']' {
-                    if current == "OPTIONS" {
-                        print!("{}", current.green().bold());
-                    } else {
-                        print!("{}", current.blue());
-                    }
-                    current.clear();
-                    current.push(ch);
-                    break;
+/// Shows the commands in quotes, like 'cargo run', in bold cyan, even when they span lines.
+/// A quote only opens after a space, so apostrophes like in "haven't" are left alone.
+fn highlight(lines: Vec<String>) -> Vec<String> {
+    let mut quoted = false;
+
+    lines
+        .into_iter()
+        .map(|line| {
+            let mut highlighted = String::new();
+            let mut part = String::new();
+            let mut previous = None;
+
+            for ch in line.chars() {
+                let opens = ch == '\'' && !quoted && previous.is_none_or(char::is_whitespace);
+                let closes = ch == '\'' && quoted;
+
+                if opens {
+                    highlighted.push_str(&part);
+                    part = ch.to_string();
+                    quoted = true;
+                } else if closes {
+                    part.push(ch);
+                    highlighted.push_str(&part.bold().cyan().to_string());
+                    part.clear();
+                    quoted = false;
+                } else {
+                    part.push(ch);
                 }
-                current.push(ch);
+                previous = Some(ch);
+            }
+
+            match quoted {
+                true => highlighted.push_str(&part.bold().cyan().to_string()),
+                false => highlighted.push_str(&part),
             }
-        } else if ch.is_whitespace() {
-            parse_command_alias(&current);
-            current.clear();
-        } else if current.trim() == env!("CARGO_BIN_NAME") {
-            print!("{}", current.bold().green());
-            current.clear();
+            highlighted
+        })
+        .collect()
+}
+
+/// `commands` grouped by their category, in the order of [`Category`], leaving out empty ones
+pub fn group_by_category(commands: Vec<CommandDoc>) -> Vec<(Category, Vec<CommandDoc>)> {
+    let mut groups: Vec<_> = Category::iter()
+        .map(|category| (category, Vec::new()))
+        .collect();
+
+    for command in commands {
+        if let Some((_, group)) = groups
+            .iter_mut()
+            .find(|(category, _)| *category == command.category)
+        {
+            group.push(command);
         }
     }
 
-    print!("{current}");
+    groups.retain(|(_, commands)| !commands.is_empty());
+    groups
 }
 
-mod parser {
-    pub struct Parser<'p> {
-        string: &'p str,
-        cursor: usize,
-    }
+/// A command or alias close to a mistyped one
+#[derive(Debug, PartialEq)]
+pub struct Suggestion {
+    pub name: String,
+    /// The command that `name` is an alias of
+    pub alias_of: Option<String>,
+}
 
-    impl<'p> Parser<'p> {
-        pub fn new(string: &'p str) -> Self {
-            Parser { string, cursor: 0 }
+/// The commands and aliases within a few typos of `typed`, closest first. Without any, the
+/// commands that share the part before the `:`, like every `migrate:*` for `migrate:run`.
+pub fn suggest_commands(typed: &str) -> Vec<Suggestion> {
+    let cli = garlic_command();
+    let candidates: Vec<_> = cli
+        .get_subcommands()
+        .filter(|command| !command.is_hide_set())
+        .flat_map(|command| {
+            let name = command.get_name();
+            std::iter::once((name, None)).chain(
+                command
+                    .get_visible_aliases()
+                    .map(move |alias| (alias, Some(name))),
+            )
+        })
+        .collect();
+
+    let max_distance = (typed.chars().count() / 3).max(2);
+    let mut close: Vec<_> = candidates
+        .iter()
+        .map(|(name, alias_of)| (edit_distance(typed, name), *name, *alias_of))
+        .filter(|(distance, _, _)| *distance <= max_distance)
+        .collect();
+    close.sort_by_key(|(distance, _, _)| *distance);
+
+    let mut suggestions: Vec<Suggestion> = Vec::new();
+    for (_, name, alias_of) in close {
+        let command = alias_of.unwrap_or(name);
+        let known = suggestions.iter().any(|suggestion| {
+            suggestion.alias_of.as_deref().unwrap_or(&suggestion.name) == command
+        });
+
+        if !known && suggestions.len() < 3 {
+            suggestions.push(Suggestion {
+                name: name.to_owned(),
+                alias_of: alias_of.map(|command| command.to_owned()),
+            });
         }
+    }
 
-        pub fn peek(&self) -> Option<char> {
-            self.string.chars().nth(self.cursor)
+    if suggestions.is_empty() {
+        if let Some((group, _)) = typed.split_once(':') {
+            suggestions = candidates
+                .iter()
+                .filter(|(name, alias_of)| {
+                    alias_of.is_none()
+                        && name
+                            .split_once(':')
+                            .is_some_and(|(prefix, _)| prefix == group)
+                })
+                .map(|(name, _)| Suggestion {
+                    name: name.to_string(),
+                    alias_of: None,
+                })
+                .collect();
         }
+    }
 
-        pub fn advance(&mut self) {
-            self.cursor += 1
-        }
+    suggestions
+}
+
+/// The Levenshtein distance, where swapping two neighbouring characters is one edit as well
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<_>, Vec<_>) = (a.chars().collect(), b.chars().collect());
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
     }
 
-    impl<'p> Iterator for Parser<'p> {
-        type Item = char;
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            rows[i][j] = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
 
-        fn next(&mut self) -> Option<Self::Item> {
-            let char = self.peek();
-            self.advance();
-            char
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                rows[i][j] = rows[i][j].min(rows[i - 2][j - 2] + 1);
+            }
         }
     }
+
+    rows[a.len()][b.len()]
 }
@@ -0,0 +1,400 @@
+use super::{dry_run, error, format_duration, shell_words::join, unix_now, utc_timestamp, Project};
+use colored::Colorize;
+use std::{
+    cell::Cell,
+    env,
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+pub const LOGS_DIR: &str = "logs";
+
+/// How many runs are kept in `.garlic/logs`, older logs are deleted
+const MAX_LOGS: usize = 100;
+
+static LOG: Mutex<Option<OpenLog>> = Mutex::new(None);
+
+thread_local! {
+    /// The pipeline step this thread runs, see [`set_step`]
+    static STEP: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+struct OpenLog {
+    file: File,
+    start: Instant,
+}
+
+/// Ends the log of a run that didn't exit early, see [`start`]
+#[must_use]
+pub struct LogGuard;
+
+impl Drop for LogGuard {
+    fn drop(&mut self) {
+        log_exit(0);
+    }
+}
+
+/// Starts the log of this garlic invocation in `.garlic/logs/`, unless it's a dry run.
+/// Every line is written right away, so a log survives a crash.
+pub fn start(project: &Project) -> Option<LogGuard> {
+    if dry_run() {
+        return None;
+    }
+
+    let dir = project.dotgarlic(LOGS_DIR);
+    fs::create_dir_all(&dir).ok()?;
+    rotate(&dir);
+
+    let started = unix_now();
+    let path = dir.join(format!(
+        "{}-{}.log",
+        utc_timestamp(started),
+        std::process::id()
+    ));
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .ok()?;
+
+    if let Ok(mut log) = LOG.lock() {
+        *log = Some(OpenLog {
+            file,
+            start: Instant::now(),
+        });
+    }
+
+    let args: Vec<_> = env::args().skip(1).collect();
+    let directory = env::current_dir().unwrap_or_default();
+    write_line(&["command", &format!("garlic {}", join(args))]);
+    write_line(&["started", &started.to_string()]);
+    write_line(&["directory", &directory.to_string_lossy()]);
+
+    Some(LogGuard)
+}
+
+/// Deletes the oldest logs so that a new one keeps the total at [`MAX_LOGS`]
+fn rotate(dir: &Path) {
+    let mut logs = log_files(dir);
+    if logs.len() < MAX_LOGS {
+        return;
+    }
+
+    logs.truncate(logs.len() + 1 - MAX_LOGS);
+    for log in logs {
+        let _ = fs::remove_file(log);
+    }
+}
+
+/// The logs in `dir`, oldest first
+fn log_files(dir: &Path) -> Vec<PathBuf> {
+    let mut logs: Vec<_> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    logs.sort();
+    logs
+}
+
+/// Fields can't contain the separators of the log format
+fn field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+fn write_line(fields: &[&str]) {
+    let line: Vec<_> = fields.iter().map(|value| field(value)).collect();
+    write_raw(&format!("{}\n", line.join("\t")));
+}
+
+fn write_raw(content: &str) {
+    if let Ok(mut log) = LOG.lock() {
+        if let Some(log) = log.as_mut() {
+            let _ = log.file.write_all(content.as_bytes());
+        }
+    }
+}
+
+/// Marks the commands this thread runs as part of `step`
+pub fn set_step(step: Option<&'static str>) {
+    STEP.set(step);
+}
+
+/// Records a command garlic ran. `result` is the exit code or why it didn't exit on its own.
+pub fn log_run(command: &str, dir: Option<&Path>, result: impl Display, duration: Duration) {
+    let step = STEP.get().unwrap_or("-");
+    let dir = dir.map(|dir| dir.to_string_lossy()).unwrap_or_default();
+    write_line(&[
+        "run",
+        step,
+        &dir,
+        &result.to_string(),
+        &duration.as_millis().to_string(),
+        command,
+    ]);
+}
+
+/// Records how a pipeline step went, with its output if it failed while running next to others
+pub fn log_step(name: &str, status: &str, duration: Option<Duration>, output: Option<&str>) {
+    let millis = duration
+        .map(|duration| duration.as_millis().to_string())
+        .unwrap_or("-".to_owned());
+    write_line(&["step", name, status, &millis]);
+
+    if let Some(output) = output {
+        write_line(&["output", name, &output.len().to_string()]);
+        write_raw(output);
+        write_raw("\n");
+    }
+}
+
+pub fn log_error(kind: &str, message: &str) {
+    write_line(&["error", kind, message]);
+}
+
+/// Records the exit code and closes the log, later lines are dropped
+pub fn log_exit(code: i32) {
+    let millis = match LOG.lock() {
+        Ok(log) => match log.as_ref() {
+            Some(log) => log.start.elapsed().as_millis(),
+            None => return,
+        },
+        Err(_) => return,
+    };
+
+    write_line(&["exit", &code.to_string(), &millis.to_string()]);
+    if let Ok(mut log) = LOG.lock() {
+        *log = None;
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RunRecord {
+    pub step: Option<String>,
+    pub dir: String,
+    pub result: String,
+    pub duration: Duration,
+    pub command: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct StepRecord {
+    pub name: String,
+    pub status: String,
+    pub duration: Option<Duration>,
+}
+
+/// A parsed log of one garlic invocation
+#[derive(Debug, Default, PartialEq)]
+pub struct LogRecord {
+    pub command: String,
+    pub started: i64,
+    pub directory: String,
+    pub runs: Vec<RunRecord>,
+    pub steps: Vec<StepRecord>,
+    /// Kind and message of every error garlic printed
+    pub errors: Vec<(String, String)>,
+    /// The output of the parallel steps that failed
+    pub outputs: Vec<(String, String)>,
+    /// The exit code and duration, `None` when garlic was killed or is still running
+    pub exit: Option<(i32, Duration)>,
+}
+
+impl LogRecord {
+    pub fn parse(content: &str) -> Self {
+        let mut record = LogRecord::default();
+        let mut rest = content;
+        let millis = |value: &str| value.parse().ok().map(Duration::from_millis);
+
+        while let Some((line, next)) = rest.split_once('\n') {
+            rest = next;
+            let fields: Vec<_> = line.split('\t').collect();
+
+            match fields.as_slice() {
+                ["command", command] => record.command = command.to_string(),
+                ["started", started] => record.started = started.parse().unwrap_or_default(),
+                ["directory", directory] => record.directory = directory.to_string(),
+                ["run", step, dir, result, duration, command] => record.runs.push(RunRecord {
+                    step: (*step != "-").then(|| step.to_string()),
+                    dir: dir.to_string(),
+                    result: result.to_string(),
+                    duration: millis(duration).unwrap_or_default(),
+                    command: command.to_string(),
+                }),
+                ["step", name, status, duration] => record.steps.push(StepRecord {
+                    name: name.to_string(),
+                    status: status.to_string(),
+                    duration: millis(duration),
+                }),
+                ["error", kind, message] => {
+                    record.errors.push((kind.to_string(), message.to_string()))
+                }
+                ["output", name, len] => {
+                    let len = len.parse().unwrap_or_default();
+                    let output = rest.get(..len).unwrap_or(rest);
+                    record.outputs.push((name.to_string(), output.to_owned()));
+                    rest = rest.get(output.len()..).unwrap_or_default();
+                    rest = rest.strip_prefix('\n').unwrap_or(rest);
+                }
+                ["exit", code, duration] => {
+                    record.exit = Some((
+                        code.parse().unwrap_or(1),
+                        millis(duration).unwrap_or_default(),
+                    ))
+                }
+                _ => {}
+            }
+        }
+
+        record
+    }
+
+    fn status(&self) -> String {
+        match self.exit {
+            Some((0, _)) => "ok".green().to_string(),
+            Some((code, _)) => format!("failed ({code})").red().to_string(),
+            None => "unfinished".yellow().to_string(),
+        }
+    }
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in UTC
+fn format_started(unix_seconds: i64) -> String {
+    let digits = format!("{:014}", utc_timestamp(unix_seconds));
+    format!(
+        "{}-{}-{} {}:{}:{}",
+        &digits[0..4],
+        &digits[4..6],
+        &digits[6..8],
+        &digits[8..10],
+        &digits[10..12],
+        &digits[12..14]
+    )
+}
+
+/// `garlic log`: lists the latest runs, or shows everything about run `run` (1 is the latest)
+pub fn print_log(project: &Project, run: Option<usize>, limit: usize) {
+    let mut logs = log_files(&project.dotgarlic(LOGS_DIR));
+    logs.reverse();
+
+    let Some(run) = run else {
+        if logs.is_empty() {
+            println!("No runs logged yet");
+        }
+
+        for (index, log) in logs.iter().enumerate().take(limit).rev() {
+            let record = LogRecord::parse(&fs::read_to_string(log).unwrap_or_default());
+            let duration = record
+                .exit
+                .map(|(_, duration)| format_duration(duration))
+                .unwrap_or("-".to_owned());
+
+            println!(
+                "{:>3}  {}  {:>8}  {:<12}  {}",
+                (index + 1).to_string().bold(),
+                format_started(record.started),
+                duration,
+                record.status(),
+                record.command.cyan()
+            );
+        }
+        return;
+    };
+
+    let Some(log) = run.checked_sub(1).and_then(|index| logs.get(index)) else {
+        error(
+            "no_run",
+            format!("There is no run {run}, {} run(s) are logged", logs.len()),
+        );
+    };
+
+    let record = LogRecord::parse(&fs::read_to_string(log).unwrap_or_default());
+    println!("{}", record.command.cyan().bold());
+    println!(
+        "Started {} UTC in {}, {}",
+        format_started(record.started),
+        record.directory,
+        record.status()
+    );
+    println!("Log: {}", log.display());
+
+    if !record.runs.is_empty() {
+        println!();
+        println!("{}", "Commands".bold().underline());
+    }
+    for run in &record.runs {
+        let step = run
+            .step
+            .as_ref()
+            .map(|step| format!("[{step}] ").blue().to_string())
+            .unwrap_or_default();
+        let result = match run.result.as_str() {
+            "0" => "exit 0".green(),
+            code if code.parse::<i32>().is_ok() => format!("exit {code}").red(),
+            other => other.red(),
+        };
+
+        println!(
+            "{step}{}  {result}, {}",
+            run.command.cyan(),
+            format_duration(run.duration)
+        );
+        println!("    in {}", run.dir);
+    }
+
+    if !record.steps.is_empty() {
+        println!();
+        println!("{}", "Steps".bold().underline());
+    }
+    for step in &record.steps {
+        println!(
+            "{:16}  {:8}  {}",
+            step.name.blue(),
+            step.status,
+            step.duration.map(format_duration).unwrap_or("-".to_owned())
+        );
+    }
+
+    if !record.errors.is_empty() {
+        println!();
+        println!("{}", "Errors".bold().underline());
+    }
+    for (kind, message) in &record.errors {
+        println!(
+            "{} (type {}): {}",
+            "Error".red(),
+            kind.cyan(),
+            message.red()
+        );
+    }
+
+    // Steps that ran alone printed straight to the terminal
+    for step in &record.steps {
+        let kept = record.outputs.iter().any(|(name, _)| *name == step.name);
+        if step.status == "failed" && !kept {
+            println!();
+            println!(
+                "The output of {} went to the terminal, it's only kept for steps that ran next to others",
+                step.name.blue()
+            );
+        }
+    }
+
+    for (step, output) in &record.outputs {
+        println!();
+        println!("{}", format!("Output of {step}").bold().underline());
+        print!("{output}");
+        if !output.ends_with('\n') {
+            println!();
+        }
+    }
+}
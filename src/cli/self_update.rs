@@ -0,0 +1,431 @@
+use super::{error, garlic_print, garlic_version, parse_version, Cmd, PassThrough};
+use colored::Colorize;
+use semver::Version;
+use serde::Deserialize;
+use std::{env, fmt, fs, path::PathBuf, str::FromStr};
+use tempdir::TempDir;
+
+const CRATE_NAME: &str = env!("CARGO_PKG_NAME");
+const CRATES_IO_INDEX: &str = "https://index.crates.io";
+const CHANGELOG_FILE: &str = "CHANGELOG.md";
+/// Picks the update source when `--source` isn't set
+pub const SOURCE_VAR: &str = "GARLIC_UPDATE_SOURCE";
+
+/// Where `garlic update` looks for versions and installs them from
+#[derive(Clone, Debug, PartialEq)]
+pub enum UpdateSource {
+    /// The crates.io index
+    CratesIo,
+    /// A sparse registry index, like a mirror of crates.io: `index:<URL>`
+    Index(String),
+    /// The version tags of a git repository: `git:<URL>`
+    Git(String),
+    /// A checkout of garlic-cli: `path:<DIR>`
+    Path(PathBuf),
+}
+
+impl FromStr for UpdateSource {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        if source == "crates-io" || source == "crates.io" {
+            return Ok(UpdateSource::CratesIo);
+        }
+
+        match source.split_once(':') {
+            Some(("index", url)) if !url.is_empty() => Ok(UpdateSource::Index(
+                url.trim_start_matches("sparse+").to_owned(),
+            )),
+            Some(("git", url)) if !url.is_empty() => Ok(UpdateSource::Git(url.to_owned())),
+            Some(("path", dir)) if !dir.is_empty() => Ok(UpdateSource::Path(dir.into())),
+            _ => Err(format!(
+                "Expected crates-io, index:<URL>, git:<URL> or path:<DIR>, got '{source}'"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for UpdateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateSource::CratesIo => write!(f, "crates-io"),
+            UpdateSource::Index(url) => write!(f, "index:{url}"),
+            UpdateSource::Git(url) => write!(f, "git:{url}"),
+            UpdateSource::Path(dir) => write!(f, "path:{}", dir.display()),
+        }
+    }
+}
+
+/// A version of garlic-cli that a source can install
+#[derive(Clone, Debug, PartialEq)]
+pub struct Release {
+    pub version: Version,
+    /// The git tag of the version, the version itself for the other sources
+    pub tag: String,
+}
+
+impl Release {
+    fn new(version: Version) -> Self {
+        Release {
+            tag: version.to_string(),
+            version,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct IndexEntry {
+    vers: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+#[derive(Deserialize)]
+struct IndexConfig {
+    dl: String,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    package: Package,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    version: String,
+}
+
+/// `garlic update`: installs the latest version of garlic from `source`, or `pinned`.
+/// Does nothing when that's the running version, unless `force` is set.
+pub fn update(
+    source: Option<UpdateSource>,
+    pinned: Option<Version>,
+    force: bool,
+    args: &PassThrough,
+) {
+    let source = source.unwrap_or_else(source_from_env);
+    let current = garlic_version();
+
+    garlic_print(format!(
+        "Looking up versions in {}",
+        source.to_string().cyan()
+    ));
+    let releases = source.releases();
+    let Some(release) = select_release(&releases, pinned.as_ref()) else {
+        match pinned {
+            Some(pinned) => error(
+                "unknown_version",
+                format!("Expected {source} to have {CRATE_NAME} {pinned}"),
+            ),
+            None => error(
+                "no_versions",
+                format!("Expected {source} to have a release of {CRATE_NAME}"),
+            ),
+        }
+    };
+
+    if !force {
+        if release.version == current {
+            garlic_print(format!("garlic {current} is up to date"));
+            return;
+        }
+        if pinned.is_none() && release.version < current {
+            garlic_print(format!(
+                "garlic {current} is newer than the latest release in {source} ({})",
+                release.version
+            ));
+            return;
+        }
+    }
+
+    garlic_print(format!(
+        "Updating garlic {} → {}",
+        current.to_string().yellow(),
+        release.version.to_string().green()
+    ));
+    print_changes(&source, &release, &current);
+
+    source.install(&release, force).args(args.all()).req();
+}
+
+fn source_from_env() -> UpdateSource {
+    match env::var(SOURCE_VAR) {
+        Ok(source) => source.parse().unwrap_or_else(|message| {
+            error("invalid_update_source", format!("{SOURCE_VAR}: {message}"))
+        }),
+        Err(_) => UpdateSource::CratesIo,
+    }
+}
+
+fn print_changes(source: &UpdateSource, release: &Release, current: &Version) {
+    let (from, to) = match release.version > *current {
+        true => (current, &release.version),
+        false => (&release.version, current),
+    };
+
+    let changes = source
+        .changelog(release)
+        .map(|changelog| changelog_range(&changelog, from, to))
+        .unwrap_or_default();
+
+    if changes.is_empty() {
+        garlic_print(format!("No changelog entries between {from} and {to}"));
+        return;
+    }
+
+    println!();
+    println!("{changes}");
+    println!();
+}
+
+/// The release to install: `pinned` if the source has it, otherwise the latest stable one
+pub fn select_release(releases: &[Release], pinned: Option<&Version>) -> Option<Release> {
+    match pinned {
+        Some(pinned) => releases
+            .iter()
+            .find(|release| release.version == *pinned)
+            .cloned(),
+        None => releases
+            .iter()
+            .filter(|release| release.version.pre.is_empty())
+            .max_by(|a, b| a.version.cmp(&b.version))
+            .cloned(),
+    }
+}
+
+/// The sections of `changelog` for the versions after `from`, up to and including `to`.
+/// Sections start with a `## ` heading containing their version, like `## [0.3.0] - 2025-04-01`.
+pub fn changelog_range(changelog: &str, from: &Version, to: &Version) -> String {
+    let mut sections = Vec::new();
+    let mut in_range = false;
+
+    for line in changelog.lines() {
+        if let Some(heading) = line.strip_prefix("## ") {
+            in_range = parse_version(&heading.replace(['[', ']'], " "))
+                .and_then(|version| Version::parse(version).ok())
+                .is_some_and(|version| *from < version && version <= *to);
+        } else if line.starts_with("# ") {
+            in_range = false;
+        }
+
+        if in_range {
+            sections.push(line);
+        }
+    }
+
+    sections.join("\n").trim().to_owned()
+}
+
+/// Where a crate's versions are listed in a sparse index, e.g. `ga/rl/garlic-cli`
+pub fn index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => format!("1/{name}"),
+        2 => format!("2/{name}"),
+        3 => format!("3/{}/{name}", &name[..1]),
+        _ => format!("{}/{}/{name}", &name[..2], &name[2..4]),
+    }
+}
+
+/// The versions in the lines of a sparse index file, without yanked ones
+pub fn parse_index(content: &str) -> Vec<Release> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<IndexEntry>(line).ok())
+        .filter(|entry| !entry.yanked)
+        .filter_map(|entry| Version::parse(&entry.vers).ok())
+        .map(Release::new)
+        .collect()
+}
+
+/// The version tags in the output of `git ls-remote --tags`, like `v0.3.0` or `0.3.0`
+pub fn parse_tags(output: &str) -> Vec<Release> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once("refs/tags/"))
+        .filter_map(|(_, tag)| {
+            let version = Version::parse(tag.trim_start_matches('v')).ok()?;
+            Some(Release {
+                version,
+                tag: tag.to_owned(),
+            })
+        })
+        .collect()
+}
+
+impl UpdateSource {
+    fn index(&self) -> &str {
+        match self {
+            UpdateSource::Index(url) => url.trim_end_matches('/'),
+            _ => CRATES_IO_INDEX,
+        }
+    }
+
+    /// Every version the source has, yanked ones excluded
+    fn releases(&self) -> Vec<Release> {
+        match self {
+            UpdateSource::CratesIo | UpdateSource::Index(_) => parse_index(&fetch(&format!(
+                "{}/{}",
+                self.index(),
+                index_path(CRATE_NAME)
+            ))),
+            UpdateSource::Git(url) => {
+                parse_tags(&lookup("git", &["ls-remote", "--tags", "--refs", url], url))
+            }
+            UpdateSource::Path(dir) => {
+                let path = dir.join("Cargo.toml");
+                let manifest = fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|content| toml::from_str::<Manifest>(&content).ok())
+                    .unwrap_or_else(|| {
+                        error(
+                            "update_source",
+                            format!(
+                                "Expected {} to be the manifest of {CRATE_NAME}",
+                                path.display()
+                            ),
+                        )
+                    });
+
+                Version::parse(&manifest.package.version)
+                    .map(|version| vec![Release::new(version)])
+                    .unwrap_or_default()
+            }
+        }
+    }
+
+    /// The command that installs `release`
+    fn install(&self, release: &Release, force: bool) -> Cmd {
+        let cmd = match self {
+            UpdateSource::CratesIo => {
+                Cmd::new("cargo", ["install", CRATE_NAME, "--version"]).arg(&release.tag)
+            }
+            UpdateSource::Index(url) => Cmd::new("cargo", ["install", CRATE_NAME, "--index"])
+                .arg(format!("sparse+{url}"))
+                .arg("--version")
+                .arg(&release.tag),
+            UpdateSource::Git(url) => Cmd::new("cargo", ["install", "--git", url.as_str()])
+                .arg("--tag")
+                .arg(&release.tag)
+                .arg(CRATE_NAME),
+            UpdateSource::Path(dir) => Cmd::new("cargo", ["install", "--path"]).arg(dir),
+        };
+
+        match force {
+            true => cmd.arg("--force"),
+            false => cmd,
+        }
+    }
+
+    /// The changelog of `release`, `None` when it doesn't have one
+    fn changelog(&self, release: &Release) -> Option<String> {
+        match self {
+            UpdateSource::Path(dir) => fs::read_to_string(dir.join(CHANGELOG_FILE)).ok(),
+            UpdateSource::Git(url) => {
+                let tempdir = TempDir::new("garlic-update").ok()?;
+                Cmd::new("git", ["clone", "--quiet", "--depth", "1", "--branch"])
+                    .arg(&release.tag)
+                    .arg(url)
+                    .arg(tempdir.path())
+                    .read_only()
+                    .capture()
+                    .ok()
+                    .filter(|output| output.success())?;
+
+                fs::read_to_string(tempdir.path().join(CHANGELOG_FILE)).ok()
+            }
+            UpdateSource::CratesIo | UpdateSource::Index(_) => {
+                let tempdir = TempDir::new("garlic-update").ok()?;
+                let archive = tempdir.path().join("garlic.crate");
+                let downloaded = Cmd::new("curl", ["-fsSL", "-o"])
+                    .arg(&archive)
+                    .arg(self.download_url(release)?)
+                    .read_only()
+                    .capture()
+                    .is_ok_and(|output| output.success());
+
+                let package_dir = format!("{CRATE_NAME}-{}", release.version);
+                let extracted = downloaded
+                    && Cmd::new("tar", ["-xzf"])
+                        .arg(&archive)
+                        .arg("-C")
+                        .arg(tempdir.path())
+                        .arg(format!("{package_dir}/{CHANGELOG_FILE}"))
+                        .read_only()
+                        .capture()
+                        .is_ok_and(|output| output.success());
+
+                match extracted {
+                    true => {
+                        fs::read_to_string(tempdir.path().join(package_dir).join(CHANGELOG_FILE))
+                            .ok()
+                    }
+                    false => None,
+                }
+            }
+        }
+    }
+
+    /// Where the `.crate` of `release` is downloaded from, as described by the index's `config.json`
+    fn download_url(&self, release: &Release) -> Option<String> {
+        let config = lookup_opt(Cmd::new(
+            "curl",
+            ["-fsSL".to_owned(), format!("{}/config.json", self.index())],
+        ))?;
+        let config: IndexConfig = serde_json::from_str(&config).ok()?;
+        let version = release.version.to_string();
+
+        let markers = ["{crate}", "{version}", "{prefix}", "{lowerprefix}"];
+        if !markers.iter().any(|marker| config.dl.contains(marker)) {
+            return Some(format!("{}/{CRATE_NAME}/{version}/download", config.dl));
+        }
+
+        let path = index_path(CRATE_NAME);
+        let prefix = path.rsplit_once('/').map_or("", |(prefix, _)| prefix);
+        Some(
+            config
+                .dl
+                .replace("{crate}", CRATE_NAME)
+                .replace("{version}", &version)
+                .replace("{prefix}", prefix)
+                .replace("{lowerprefix}", prefix),
+        )
+    }
+}
+
+fn fetch(url: &str) -> String {
+    lookup("curl", &["-fsSL", url], url)
+}
+
+/// Runs `program` to look up versions in `source`, also with `--dry-run`
+fn lookup(program: &str, args: &[&str], source: &str) -> String {
+    let output = Cmd::new(program, args)
+        .read_only()
+        .capture()
+        .unwrap_or_else(|e| {
+            error(
+                "update_source",
+                format!("Expected {program} to be installed to look up versions: {e}"),
+            )
+        });
+
+    if !output.success() {
+        error(
+            "update_source",
+            format!(
+                "Couldn't look up versions in {source}: {}",
+                output.stderr.trim()
+            ),
+        );
+    }
+
+    output.stdout
+}
+
+fn lookup_opt(cmd: Cmd) -> Option<String> {
+    cmd.read_only()
+        .capture()
+        .ok()
+        .filter(|output| output.success())
+        .map(|output| output.stdout)
+}
@@ -0,0 +1,93 @@
+use std::{borrow::Cow, fmt::Display};
+
+#[derive(Debug, PartialEq)]
+pub enum SplitError {
+    UnterminatedQuote(char),
+    TrailingBackslash,
+}
+
+impl Display for SplitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SplitError::UnterminatedQuote(quote) => write!(f, "missing closing {quote}"),
+            SplitError::TrailingBackslash => write!(f, "nothing to escape after \\"),
+        }
+    }
+}
+
+/// Splits a command line into arguments like a POSIX shell would, without expanding anything.
+///
+/// input: `cargo test -- --test-threads 1 "name with spaces"`
+pub fn split(line: &str) -> Result<Vec<String>, SplitError> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            ch if ch.is_whitespace() => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(ch) => word.push(ch),
+                        None => return Err(SplitError::UnterminatedQuote('\'')),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(ch @ ('"' | '\\' | '$' | '`')) => word.push(ch),
+                            Some('\n') => {}
+                            Some(ch) => {
+                                word.push('\\');
+                                word.push(ch);
+                            }
+                            None => return Err(SplitError::UnterminatedQuote('"')),
+                        },
+                        Some(ch) => word.push(ch),
+                        None => return Err(SplitError::UnterminatedQuote('"')),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(ch) => word.get_or_insert_with(String::new).push(ch),
+                None => return Err(SplitError::TrailingBackslash),
+            },
+            ch => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+
+    words.extend(word);
+    Ok(words)
+}
+
+/// Quotes `word` so that `split` (or a shell) reads it back as a single argument.
+pub fn quote(word: &str) -> Cow<'_, str> {
+    let safe = |ch: char| ch.is_ascii_alphanumeric() || "-_./:=@%+,^".contains(ch);
+
+    if !word.is_empty() && word.chars().all(safe) {
+        Cow::Borrowed(word)
+    } else {
+        Cow::Owned(format!("'{}'", word.replace('\'', "'\\''")))
+    }
+}
+
+/// Joins arguments into a command line that can be pasted into a shell.
+pub fn join<S: AsRef<str>>(words: impl IntoIterator<Item = S>) -> String {
+    words
+        .into_iter()
+        .map(|word| quote(word.as_ref()).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
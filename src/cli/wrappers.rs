@@ -0,0 +1,50 @@
+use super::{Cmd, GarlicCommand, Project};
+
+/// The arguments a wrapper passes on to the command it runs
+#[derive(clap::Args, Clone, Debug, Default, PartialEq)]
+pub struct PassThrough {
+    /// Arguments for the wrapped command, prefer passing them after `--`
+    #[arg(long, allow_hyphen_values = true, num_args = 0..)]
+    args: Vec<String>,
+    /// Passed to the wrapped command as they are, e.g. `garlic server -- --release`
+    #[arg(last = true, value_name = "ARGS")]
+    rest: Vec<String>,
+}
+
+impl PassThrough {
+    /// `--args` first, then everything after `--`
+    pub fn all(&self) -> impl Iterator<Item = &String> {
+        self.args.iter().chain(&self.rest)
+    }
+}
+
+impl GarlicCommand {
+    /// The command a wrapper runs, `None` for the other commands
+    pub fn wrapped_cmd(&self, project: &Project) -> Option<Cmd> {
+        use GarlicCommand as Cc;
+
+        let cmd = match self {
+            Cc::RunBackend { args } => project.cmd("cargo run").args(args.all()),
+            Cc::RunFrontend { args } => project.exec("vite dev").args(args.all()),
+            Cc::AddCrate {
+                name,
+                package,
+                args,
+            } => project
+                .cmd("cargo add")
+                .arg(name)
+                .arg("--package")
+                .arg(package)
+                .args(args.all()),
+            Cc::Prepare { args } => project
+                .cmd("cargo sqlx prepare --workspace")
+                .args(args.all()),
+            Cc::Preview { args } => project.exec("vite preview").args(args.all()),
+            Cc::TestUnit { args } => project.exec("vitest").args(args.all()),
+            Cc::Migrate { args } => project.cmd("cargo sqlx migrate").args(args.all()),
+            _ => return None,
+        };
+
+        Some(cmd)
+    }
+}
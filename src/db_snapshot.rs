@@ -0,0 +1,310 @@
+use crate::{
+    dry_run, dry_run_print, error, garlic_print,
+    init_db::{connect, database_url, disconnect_all, drop_database, quote_ident, MAX_IDENT_LEN},
+    unix_now, Project,
+};
+use colored::Colorize;
+use postgres::Client;
+use std::{fmt::Write as _, fs};
+
+/// Metadata of the snapshots, stored in `.garlic/snapshots`
+const SNAPSHOTS_FILE: &str = "snapshots";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub name: String,
+    /// The database holding the copy
+    pub database: String,
+    /// The database the copy was taken from
+    pub source: String,
+    /// Seconds since the unix epoch
+    pub created: i64,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Snapshots(pub Vec<Snapshot>);
+
+impl Snapshots {
+    /// Parses the tab separated lines of the snapshots file
+    pub fn parse(content: &str) -> Snapshots {
+        let snapshots = content
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                Some(Snapshot {
+                    name: fields.next()?.to_owned(),
+                    database: fields.next()?.to_owned(),
+                    source: fields.next()?.to_owned(),
+                    created: fields.next()?.parse().ok()?,
+                })
+            })
+            .collect();
+
+        Snapshots(snapshots)
+    }
+
+    pub fn to_file_string(&self) -> String {
+        let mut output = String::from("# name\tdatabase\tsource\tcreated\n");
+        for snapshot in &self.0 {
+            let _ = writeln!(
+                output,
+                "{}\t{}\t{}\t{}",
+                snapshot.name, snapshot.database, snapshot.source, snapshot.created
+            );
+        }
+        output
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Snapshot> {
+        self.0.iter().find(|snapshot| snapshot.name == name)
+    }
+
+    fn load(project: &Project) -> Snapshots {
+        match fs::read_to_string(project.dotgarlic(SNAPSHOTS_FILE)) {
+            Ok(content) => Snapshots::parse(&content),
+            Err(_) => Snapshots::default(),
+        }
+    }
+
+    fn save(&self, project: &Project) {
+        fs::write(project.dotgarlic(SNAPSHOTS_FILE), self.to_file_string())
+            .expect("Expected to be able to save snapshots");
+    }
+
+    fn insert(&mut self, snapshot: Snapshot) {
+        self.remove(&snapshot.name);
+        self.0.push(snapshot);
+    }
+
+    fn remove(&mut self, name: &str) -> Option<Snapshot> {
+        let index = self.0.iter().position(|snapshot| snapshot.name == name)?;
+        Some(self.0.remove(index))
+    }
+}
+
+/// The name of the database that holds snapshot `name` of `source`
+pub fn snapshot_database_name(source: &str, name: &str) -> Result<String, String> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-')
+    {
+        return Err(format!(
+            "Snapshot name '{name}' may only contain letters, digits, '_' and '-'"
+        ));
+    }
+
+    let database = format!("{source}__snap_{name}");
+    if database.len() > MAX_IDENT_LEN {
+        return Err(format!(
+            "Snapshot database '{database}' is longer than {MAX_IDENT_LEN} characters"
+        ));
+    }
+
+    Ok(database)
+}
+
+/// The temporary name a database is restored under before it replaces `database`
+fn restore_database_name(database: &str) -> String {
+    let suffix = "__restoring";
+    let mut end = database.len().min(MAX_IDENT_LEN - suffix.len());
+    while !database.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{suffix}", &database[..end])
+}
+
+pub fn snapshot(project: &Project, name: &str, force: bool) {
+    let url = database_url(&project.root);
+    let database =
+        snapshot_database_name(&url.database, name).unwrap_or_else(|e| error("invalid_name", e));
+
+    let mut snapshots = Snapshots::load(project);
+    if snapshots.get(name).is_some() && !force {
+        error(
+            "snapshot_exists",
+            format!("Snapshot '{name}' already exists. Use --force to overwrite it"),
+        );
+    }
+
+    if dry_run() {
+        dry_run_print(format!(
+            "Would copy {} to {database} as snapshot '{name}'",
+            url.database
+        ));
+        return;
+    }
+
+    let mut client = connect(&url.with_database("postgres"));
+    if database_exists(&mut client, &database) {
+        if snapshots.get(name).is_none() && !force {
+            error(
+                "database_exists",
+                format!(
+                    "{database} already exists but isn't a snapshot. Use --force to replace it"
+                ),
+            );
+        }
+        drop_database(&mut client, &database).unwrap_or_else(|e| drop_failed(&database, e));
+    }
+
+    copy_database(&mut client, &url.database, &database);
+
+    snapshots.insert(Snapshot {
+        name: name.to_owned(),
+        database,
+        source: url.database,
+        created: unix_now(),
+    });
+    snapshots.save(project);
+
+    garlic_print(format!("Saved snapshot '{}'", name.cyan()));
+}
+
+pub fn restore(project: &Project, name: &str) {
+    let url = database_url(&project.root);
+    let snapshots = Snapshots::load(project);
+    let Some(snapshot) = snapshots.get(name) else {
+        error(
+            "no_snapshot",
+            format!("Snapshot '{name}' not found. Run garlic db:snapshots to see all snapshots"),
+        );
+    };
+
+    let mut client = connect(&url.with_database("postgres"));
+    if !database_exists(&mut client, &snapshot.database) {
+        error(
+            "no_snapshot",
+            format!(
+                "The database of snapshot '{name}' ({}) no longer exists",
+                snapshot.database
+            ),
+        );
+    }
+
+    if snapshot.source != url.database {
+        garlic_print(format!(
+            "Snapshot '{name}' was taken from {}, restoring into {}",
+            snapshot.source, url.database
+        ));
+    }
+
+    if dry_run() {
+        dry_run_print(format!(
+            "Would replace {} with a copy of {}",
+            url.database, snapshot.database
+        ));
+        return;
+    }
+
+    // The copy is made next to the database first, so a failed copy leaves it as it was
+    let restoring = restore_database_name(&url.database);
+    drop_database(&mut client, &restoring).unwrap_or_else(|e| drop_failed(&restoring, e));
+    copy_database(&mut client, &snapshot.database, &restoring);
+
+    drop_database(&mut client, &url.database).unwrap_or_else(|e| drop_failed(&url.database, e));
+    client
+        .batch_execute(&format!(
+            "ALTER DATABASE {} RENAME TO {}",
+            quote_ident(&restoring),
+            quote_ident(&url.database)
+        ))
+        .unwrap_or_else(|e| {
+            error(
+                "restore_failed",
+                format!(
+                    "Couldn't rename {restoring} to {}: {e}. The snapshot is in {restoring}",
+                    url.database
+                ),
+            )
+        });
+
+    garlic_print(format!(
+        "Restored {} to snapshot '{}'",
+        url.database,
+        name.cyan()
+    ));
+}
+
+pub fn list(project: &Project) {
+    let snapshots = Snapshots::load(project);
+    if snapshots.0.is_empty() {
+        garlic_print("No snapshots yet. Run garlic db:snapshot <NAME> to take one");
+        return;
+    }
+
+    let width = snapshots
+        .0
+        .iter()
+        .map(|snapshot| snapshot.name.len())
+        .max()
+        .unwrap_or(0);
+
+    let now = unix_now();
+    for snapshot in &snapshots.0 {
+        println!(
+            "{}  {} (of {}, {})",
+            format!("{:width$}", snapshot.name).blue(),
+            snapshot.database,
+            snapshot.source,
+            ago(now - snapshot.created)
+        );
+    }
+}
+
+pub fn delete(project: &Project, name: &str) {
+    let url = database_url(&project.root);
+    let mut snapshots = Snapshots::load(project);
+    let Some(snapshot) = snapshots.remove(name) else {
+        error("no_snapshot", format!("Snapshot '{name}' not found"));
+    };
+
+    if dry_run() {
+        dry_run_print(format!("Would drop {}", snapshot.database));
+        return;
+    }
+
+    let mut client = connect(&url.with_database("postgres"));
+    drop_database(&mut client, &snapshot.database)
+        .unwrap_or_else(|e| drop_failed(&snapshot.database, e));
+    snapshots.save(project);
+
+    garlic_print(format!("Deleted snapshot '{}'", name.cyan()));
+}
+
+fn ago(seconds: i64) -> String {
+    match seconds {
+        ..60 => "just now".to_owned(),
+        60..3600 => format!("{} minutes ago", seconds / 60),
+        3600..86400 => format!("{} hours ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
+    }
+}
+
+fn database_exists(client: &mut Client, database: &str) -> bool {
+    client
+        .query_opt("SELECT 1 FROM pg_database WHERE datname = $1", &[&database])
+        .expect("expected to be able to run query")
+        .is_some()
+}
+
+fn drop_failed(database: &str, e: postgres::Error) -> ! {
+    error("drop_failed", format!("Couldn't drop {database}: {e}"))
+}
+
+fn copy_database(client: &mut Client, from: &str, to: &str) {
+    disconnect_all(client, from).unwrap_or_else(|e| {
+        error(
+            "copy_failed",
+            format!("Couldn't close the connections to {from}: {e}"),
+        )
+    });
+    client
+        .batch_execute(&format!(
+            "CREATE DATABASE {} TEMPLATE {}",
+            quote_ident(to),
+            quote_ident(from)
+        ))
+        .unwrap_or_else(|e| error("copy_failed", format!("Couldn't copy {from} to {to}: {e}")));
+}
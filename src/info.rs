@@ -0,0 +1,314 @@
+use crate::{garlic_version, parse_version, Cmd, DotEnvs, Manifest, PostgresUrl, Project};
+use colored::Colorize;
+use postgres::{Client, NoTls};
+use serde::Serialize;
+use std::{collections::BTreeMap, env, fmt::Display, path::Path};
+
+/// What `garlic info` shows, also as `--json` for bug reports
+#[derive(Serialize)]
+pub struct Info {
+    pub package: PackageInfo,
+    pub build: BuildInfo,
+    pub toolchain: ToolchainInfo,
+    /// `None` outside of projects
+    pub project: Option<ProjectInfo>,
+}
+
+#[derive(Serialize)]
+pub struct PackageInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub authors: &'static str,
+    pub license: &'static str,
+    pub description: &'static str,
+    pub repository: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct BuildInfo {
+    pub location: String,
+    pub profile: &'static str,
+    pub arch: &'static str,
+    pub os: &'static str,
+}
+
+/// The tools of the project, or of the current directory outside of projects
+#[derive(Serialize)]
+pub struct ToolchainInfo {
+    /// The rustup toolchain, like `stable-x86_64-unknown-linux-gnu`
+    pub rust_toolchain: Option<String>,
+    pub rustc: Option<String>,
+    /// The target rustc builds for by default
+    pub rust_target: Option<String>,
+    pub bun: Option<String>,
+    pub node: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ProjectInfo {
+    pub root: String,
+    pub frontend: String,
+    pub package_manager: String,
+    pub workspace_packages: Vec<String>,
+    pub garlic_version: Option<String>,
+    /// Whether the running garlic is in `garlic_version`
+    pub garlic_compatible: bool,
+    pub template_revision: Option<String>,
+    /// The keys in `.env`, with their values masked
+    pub env: BTreeMap<String, String>,
+    /// `None` without a `DATABASE_URL`
+    pub database: Option<DatabaseInfo>,
+}
+
+#[derive(Serialize)]
+pub struct DatabaseInfo {
+    pub host: String,
+    pub port: u16,
+    pub database: String,
+    pub reachable: bool,
+    /// Why the database isn't reachable
+    pub error: Option<String>,
+}
+
+/// `garlic info`: prints what garlic knows about itself, the toolchain and `project`
+pub fn print_info(project: Option<&Project>, json: bool) {
+    let info = Info::collect(project);
+
+    match json {
+        true => println!(
+            "{}",
+            serde_json::to_string_pretty(&info).expect("Expected the info to serialize")
+        ),
+        false => info.print(),
+    }
+}
+
+/// Hides `.env` values, only showing whether they are set
+pub fn mask(value: &str) -> String {
+    match value.is_empty() {
+        true => String::new(),
+        false => "********".to_owned(),
+    }
+}
+
+/// The keys of `envs` with [`mask`]ed values
+pub fn masked_env(envs: &DotEnvs) -> BTreeMap<String, String> {
+    envs.iter()
+        .map(|(key, value)| (key.clone(), mask(value)))
+        .collect()
+}
+
+impl Info {
+    pub fn collect(project: Option<&Project>) -> Self {
+        let dir = project.map_or(Path::new("."), |project| &project.root);
+
+        Info {
+            package: PackageInfo {
+                name: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+                authors: env!("CARGO_PKG_AUTHORS"),
+                license: env!("CARGO_PKG_LICENSE"),
+                description: env!("CARGO_PKG_DESCRIPTION"),
+                repository: env!("CARGO_PKG_REPOSITORY"),
+            },
+            build: BuildInfo {
+                location: env::current_exe()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                profile: match cfg!(debug_assertions) {
+                    true => "debug",
+                    false => "release",
+                },
+                arch: env::consts::ARCH,
+                os: env::consts::OS,
+            },
+            toolchain: ToolchainInfo::collect(dir),
+            project: project.map(ProjectInfo::collect),
+        }
+    }
+
+    fn print(&self) {
+        let package = &self.package;
+        println!("{} v{}", package.name.bold().green(), package.version);
+
+        section("PACKAGE");
+        row("Authors", package.authors);
+        row("License", package.license);
+        row("Description", package.description);
+        row("Repository", package.repository);
+
+        section("BUILD");
+        row("Location", &self.build.location);
+        row("Profile", self.build.profile);
+        row("Target architecture", self.build.arch);
+        row("Target OS", self.build.os);
+
+        let toolchain = &self.toolchain;
+        section("TOOLCHAIN");
+        row("Rust toolchain", or_missing(&toolchain.rust_toolchain));
+        row("rustc", or_missing(&toolchain.rustc));
+        row("Rust target", or_missing(&toolchain.rust_target));
+        row("Bun", or_missing(&toolchain.bun));
+        row("Node", or_missing(&toolchain.node));
+
+        let Some(project) = &self.project else {
+            return;
+        };
+
+        section("PROJECT");
+        row("Root", &project.root);
+        row("Frontend", &project.frontend);
+        row("Package manager", &project.package_manager);
+        match project.workspace_packages.is_empty() {
+            true => row("Workspace packages", "not found"),
+            false => row("Workspace packages", project.workspace_packages.join(", ")),
+        }
+        match &project.garlic_version {
+            Some(range) => {
+                let status = match project.garlic_compatible {
+                    true => "(compatible)".green(),
+                    false => "(incompatible)".red(),
+                };
+                println!("Garlic versions: {} {status}", range.blue());
+            }
+            None => row("Garlic versions", "not recorded"),
+        }
+        row(
+            "Template revision",
+            project
+                .template_revision
+                .as_deref()
+                .unwrap_or("not recorded"),
+        );
+
+        section("ENVIRONMENT");
+        if project.env.is_empty() {
+            println!("No variables in .env");
+        }
+        for (key, value) in &project.env {
+            match value.is_empty() {
+                true => println!("{key}: {}", "empty".yellow()),
+                false => println!("{key}: {}", value.blue()),
+            }
+        }
+
+        section("DATABASE");
+        match &project.database {
+            Some(database) => {
+                row(
+                    "Server",
+                    format!("{}:{}/{}", database.host, database.port, database.database),
+                );
+                match &database.error {
+                    None => println!("Status: {}", "reachable".green()),
+                    Some(error) => println!("Status: {} ({error})", "unreachable".red()),
+                }
+            }
+            None => row("Server", "DATABASE_URL is not set"),
+        }
+    }
+}
+
+impl ToolchainInfo {
+    fn collect(dir: &Path) -> Self {
+        let rustc = output(dir, "rustc", &["-vV"]);
+        let field = |name: &str| {
+            rustc.as_deref().and_then(|rustc| {
+                rustc
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .map(|value| value.trim().to_owned())
+            })
+        };
+
+        ToolchainInfo {
+            rust_toolchain: output(dir, "rustup", &["show", "active-toolchain"])
+                .and_then(|output| output.split_whitespace().next().map(str::to_owned)),
+            rustc: field("release:"),
+            rust_target: field("host:"),
+            bun: version(dir, "bun"),
+            node: version(dir, "node"),
+        }
+    }
+}
+
+impl ProjectInfo {
+    fn collect(project: &Project) -> Self {
+        let manifest = Manifest::load(&project.root);
+        let current = garlic_version();
+        let envs = DotEnvs::load(project.root.join(".env")).ok();
+
+        // Only `.env`, like the database commands
+        let database_url = envs
+            .as_ref()
+            .and_then(|envs| envs.get("DATABASE_URL").cloned())
+            .filter(|url| url.starts_with("postgres://"));
+
+        ProjectInfo {
+            root: project.root.display().to_string(),
+            frontend: project.app.display().to_string(),
+            package_manager: project.package_manager.to_string(),
+            workspace_packages: project.workspace_packages(),
+            garlic_version: manifest.garlic_version.as_ref().map(ToString::to_string),
+            garlic_compatible: manifest.supports(&current),
+            template_revision: manifest.template_revision,
+            env: envs.as_ref().map(masked_env).unwrap_or_default(),
+            database: database_url
+                .map(|url| DatabaseInfo::check(PostgresUrl::parse_env_connection_string(&url))),
+        }
+    }
+}
+
+impl DatabaseInfo {
+    fn check(url: PostgresUrl) -> Self {
+        let error = match url.is_running() {
+            false => Some(format!("nothing listens on {}:{}", url.host, url.port)),
+            true => Client::connect(
+                &format!("{} connect_timeout=2", url.to_database_connection_params()),
+                NoTls,
+            )
+            .err()
+            .map(|e| e.to_string()),
+        };
+
+        DatabaseInfo {
+            host: url.host,
+            port: url.port,
+            database: url.database,
+            reachable: error.is_none(),
+            error,
+        }
+    }
+}
+
+/// The output of a tool that is only asked about itself, `None` when it isn't installed
+fn output(dir: &Path, program: &str, args: &[&str]) -> Option<String> {
+    Cmd::new(program, args)
+        .dir(dir)
+        .read_only()
+        .capture()
+        .ok()
+        .filter(|output| output.success())
+        .map(|output| output.stdout)
+}
+
+fn version(dir: &Path, program: &str) -> Option<String> {
+    output(dir, program, &["--version"])
+        .as_deref()
+        .and_then(parse_version)
+        .map(str::to_owned)
+}
+
+fn section(name: &str) {
+    println!();
+    println!("{}", format!("--{name}--").blue());
+}
+
+fn row(name: &str, value: impl Display) {
+    println!("{name}: {}", value.to_string().blue());
+}
+
+fn or_missing(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("not found")
+}
@@ -1,22 +1,24 @@
 use crate::{
-    error_opt, find_dotgarlic_directory, garlic_print, random_jwt_secret, DotEnvs, PostgresUrl,
+    copy_file, dry_run, dry_run_print, error, error_opt, garlic_print, random_jwt_secret, DotEnvs,
+    PostgresUrl, Project,
 };
 use dialoguer::{Confirm, Input};
 use postgres::{Client, NoTls};
-use std::{env::current_dir, fs::copy};
+use std::path::Path;
 
-pub fn init_db_inner() {
-    // setup
-    let current_dir =
-        find_dotgarlic_directory().unwrap_or(current_dir().expect("Expected a current directory"));
-
-    if !current_dir.join(".env").exists() {
-        copy(current_dir.join(".env.example"), current_dir.join(".env"))
+pub fn init_db_inner(project: &Project) {
+    let mut env_file = project.root.join(".env");
+    if !env_file.exists() {
+        copy_file(project.root.join(".env.example"), &env_file)
             .expect("Expected to be able to copy to .env");
+
+        if dry_run() {
+            env_file = project.root.join(".env.example");
+        }
     }
 
-    let mut envs = DotEnvs::load(current_dir.join(".env")).expect("Expected envs");
-    let example_db_string = DotEnvs::load(current_dir.join(".env.example"))
+    let mut envs = DotEnvs::load(&env_file).expect("Expected envs");
+    let example_db_string = DotEnvs::load(project.root.join(".env.example"))
         .expect("Expected envs")
         .remove("DATABASE_URL")
         .expect("Expected DATABASE_URL in .env.example");
@@ -28,7 +30,7 @@ pub fn init_db_inner() {
         envs.insert("JWT_SECRET".to_owned(), random_jwt_secret());
     }
 
-    envs.save(current_dir.join(".env"))
+    envs.save(project.root.join(".env"))
         .expect("Expected to be able to save");
 
     let mut database_url = envs
@@ -77,7 +79,7 @@ pub fn init_db_inner() {
 
         envs.insert("DATABASE_URL".to_owned(), url.to_env_connection_string());
 
-        envs.save(current_dir.join(".env"))
+        envs.save(project.root.join(".env"))
             .expect("Expected to be able to save");
     }
 
@@ -107,6 +109,11 @@ pub fn setup_postgres_database(url: PostgresUrl) -> bool {
                         .interact()
                         .expect("Expected interaction")
                     {
+                        if dry_run() {
+                            dry_run_print(format!("Would create database {}", url.database));
+                            return true;
+                        }
+
                         let create_db_query = format!("CREATE DATABASE {}", url.database);
                         client
                             .execute(&create_db_query, &[])
@@ -115,7 +122,7 @@ pub fn setup_postgres_database(url: PostgresUrl) -> bool {
                         garlic_print(format!("Created {}", url.database));
                         true
                     } else {
-                        garlic_print(format!("You'll have to manually create the database."));
+                        garlic_print("You'll have to manually create the database.");
                         true
                     }
                 } else {
@@ -136,3 +143,66 @@ pub fn setup_postgres_database(url: PostgresUrl) -> bool {
         false
     }
 }
+
+/// Reads the `DATABASE_URL` of the project's `.env`.
+/// A `DATABASE_URL` in the environment is ignored, so a stray export can't point
+/// destructive commands at another database.
+pub fn database_url(root: impl AsRef<Path>) -> PostgresUrl {
+    let envs = DotEnvs::load(root.as_ref().join(".env"))
+        .unwrap_or_else(|e| error("no_env", format!("Couldn't read .env: {e}")));
+
+    match envs.get("DATABASE_URL") {
+        Some(url) if !url.is_empty() => PostgresUrl::parse_env_connection_string(url),
+        _ => error(
+            "no_database_url",
+            "DATABASE_URL is not set in .env. Run garlic init:db first",
+        ),
+    }
+}
+
+/// Connects to the database of `url`, exiting when postgres can't be reached.
+pub fn connect(url: &PostgresUrl) -> Client {
+    if !url.is_running() {
+        error(
+            "not_detected",
+            format!("Postgres not detected at {}:{}.", url.host, url.port),
+        );
+    }
+
+    Client::connect(&url.to_database_connection_params(), NoTls)
+        .unwrap_or_else(|e| error("no_connection", format!("Couldn't connect to client: {e}")))
+}
+
+/// Postgres truncates identifiers longer than this
+pub const MAX_IDENT_LEN: usize = 63;
+
+/// Postgres refuses to copy or drop a database while others are connected to it,
+/// so the connections of a running dev server are closed first.
+pub fn disconnect_all(client: &mut Client, database: &str) -> Result<(), postgres::Error> {
+    let closed = client
+        .query(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity \
+             WHERE datname = $1 AND pid <> pg_backend_pid()",
+            &[&database],
+        )?
+        .len();
+
+    if closed > 0 {
+        garlic_print(format!("Closed {closed} connection(s) to {database}"));
+    }
+    Ok(())
+}
+
+/// Drops `database` if it exists, closing its connections first
+pub fn drop_database(client: &mut Client, database: &str) -> Result<(), postgres::Error> {
+    disconnect_all(client, database)?;
+    client.batch_execute(&format!(
+        "DROP DATABASE IF EXISTS {}",
+        quote_ident(database)
+    ))
+}
+
+/// Quotes a postgres identifier such as a database name.
+pub fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
@@ -1,186 +1,312 @@
 mod cli;
+mod db_snapshot;
+mod info;
 mod init_db;
+mod migrate;
+mod seed;
+mod test_db;
 #[cfg(test)]
 mod tests;
+mod upgrade;
 
 use clap::Parser;
 pub use cli::*;
 use dialoguer::{Confirm, Input};
-use std::{
-    fs::{copy, remove_dir_all},
-    path::Path,
-};
+use std::{fs::remove_dir_all, path::Path, time::Duration};
 use tempdir::TempDir;
 pub use GarlicCommand as Cc;
 
 fn main() {
-    let garlic = GarlicParser::parse();
+    completions::complete();
+
+    let garlic = GarlicParser::try_parse().unwrap_or_else(|e| GarlicParser::parse_error(e));
+    set_dry_run(garlic.dry_run);
 
     let command = match garlic.command {
-        Some(Cc::Help) => GarlicParser::render_help(None),
+        Some(Cc::Help {
+            format: Some(format),
+            ..
+        }) => {
+            print!("{}", render_docs(format));
+            return;
+        }
+        Some(Cc::Help {
+            category: Some(category),
+            ..
+        }) => GarlicParser::render_category(category),
+        Some(Cc::Help { .. }) => GarlicParser::render_help(None),
         Some(cmd) if !garlic.help => cmd,
         c => GarlicParser::render_help(c),
     };
 
-    match &command {
-        Cc::Init { .. } => { /* init command, we don't expect a .garlic at this point */ }
-        Cc::Help => {}
-        Cc::Info => {}
-        _ => {
-            if find_dotgarlic_directory().is_none() {
-                error_opt("no_dotgarlic", ".garlic File not found. This file is used as an anchor for projects so you can run garlic commands in subdirectories.");
-            }
+    match command {
+        Cc::Help { .. } => {}
+        Cc::Info { json } => {
+            // Outside of projects info still works, unless one was asked for with `--project`
+            let project = match garlic.project.as_deref() {
+                Some(path) => Some(Project::require(Some(path))),
+                None => Project::find(None),
+            };
+            info::print_info(
+                project
+                    .map(|project| project.with_package_manager(garlic.pm))
+                    .as_ref(),
+                json,
+            )
         }
+        Cc::Completions { shell } => completions::print_completions(shell),
+        // There's no project yet, `--project` is the directory to create it in
+        Cc::Init { location } => init(garlic.project.as_deref(), location.as_deref(), garlic.pm),
+        // Updating garlic works outside of projects too
+        Cc::UpdateSelf {
+            version,
+            source,
+            force,
+            args,
+        } => self_update::update(source, version, force, &args),
+        command => {
+            let project = Project::require(garlic.project.as_deref());
+            Manifest::load(&project.root).check_garlic_version(garlic.strict_version);
+            run(&project.with_package_manager(garlic.pm), command)
+        }
+    }
+}
+
+fn init(base: Option<&Path>, location: Option<&str>, pm: Option<PackageManager>) {
+    let location = base.unwrap_or(Path::new(".")).join(location.unwrap_or("."));
+    if !folder_empty(&location)
+        && !Confirm::new()
+            .with_prompt("Folder is not empty. Continue?")
+            .interact()
+            .expect("Expected interaction")
+    {
+        error("not_empty", "Folder is not empty. Exiting");
+    }
+
+    let pm = pm.unwrap_or_default();
+    init_inner(pm);
+
+    let tempdir =
+        TempDir::new("garlic-init").expect("Expected to be able to create temporary directory");
+    let temp_str = tempdir.path().to_str().expect("expected utf-8 path");
+
+    Cmd::new("git", ["clone", TEMPLATE_REPO, temp_str]).req();
+    let template_revision = Cmd::run("git rev-parse HEAD")
+        .dir(temp_str)
+        .capture()
+        .ok()
+        .map(|output| output.stdout.trim().to_owned())
+        .filter(|revision| !revision.is_empty());
+
+    if !dry_run() {
+        remove_dir_all(format!("{temp_str}/.git")).expect("Expected to remove original .git");
+    }
+
+    copy_dir_contents(temp_str, &location).expect("Expected to be able to clone directory");
+
+    Manifest::new(pm, template_revision).save(&location);
+
+    let project = Project::at(&location).with_package_manager(Some(pm));
+    project.cmd("git init").req();
+    project.install().req();
+
+    copy_file(location.join(".env.example"), location.join(".env"))
+        .expect("Expected to be able to copy to .env");
+
+    if dry_run() {
+        dry_run_print("Would then ask for the OpenAPI name and set up the database");
+        return;
+    }
+
+    let mut envs = DotEnvs::load(location.join(".env")).expect("Expected envs");
+
+    let app_name = Input::new()
+        .with_prompt("OpenAPI name?")
+        .default("MyGarlic".to_owned())
+        .interact()
+        .expect("Expected interaction");
+
+    *envs
+        .get_mut("OPENAPI_TITLE")
+        .expect("Expected OPENAPI_TITLE in .env") = app_name;
+
+    envs.save(location.join(".env"))
+        .expect("Expected to be able to save");
+
+    if Confirm::new()
+        .with_prompt("Set up database now? (Requires that postgres is running & can be done later)")
+        .interact()
+        .expect("Expected interaction")
+    {
+        init_db::init_db_inner(&project);
+    } else {
+        envs.insert("JWT_SECRET".to_owned(), random_jwt_secret());
+
+        envs.save(location.join(".env"))
+            .expect("Expected to be able to save");
+
+        garlic_print("Make sure to setup your postgres instance");
+        garlic_print("And set your .env's DATABASE_URL manually OR run garlic init:db");
     }
 
+    garlic_print("🧄 Done!");
+
+    println!();
+    garlic_print("Run `garlic server` to start the server!");
+    garlic_print("Run `garlic dev --open` to run and open the site!");
+}
+
+fn run(project: &Project, command: GarlicCommand) {
+    let _log = match command {
+        Cc::Log { .. } => None,
+        _ => run_log::start(project),
+    };
+
     match command {
-        Cc::Help => {}
-        Cc::Info => print_info(),
-        Cc::Init { location } => {
-            let location = Path::new(location.as_deref().unwrap_or("."));
-            if !folder_empty(location)
-                && !Confirm::new()
-                    .with_prompt("Folder is not empty. Continue?")
-                    .interact()
-                    .expect("Expected interaction")
-            {
-                error("not_empty", "Folder is not empty. Exiting");
-            }
-
-            init_inner();
-
-            let tempdir = TempDir::new("garlic-init")
-                .expect("Expected to be able to create temporary directory");
-            let temp_str = tempdir.path().to_str().expect("expected utf-8 path");
-
-            Cmd::run("git clone https://github.com/eatenpancreas/garlic.git")
-                .arg(temp_str)
-                .req();
-
-            remove_dir_all(format!("{temp_str}/.git")).expect("Expected to remove original .git");
-
-            copy_dir_contents(temp_str, location).expect("Expected to be able to clone directory");
-
-            Cmd::run("git init").req();
-            Cmd::run("bun install").app().req();
-
-            copy(location.join(".env.example"), location.join(".env"))
-                .expect("Expected to be able to copy to .env");
-
-            let mut envs = DotEnvs::load(location.join(".env")).expect("Expected envs");
-
-            let app_name = Input::new()
-                .with_prompt("OpenAPI name?")
-                .default("MyGarlic".to_owned())
-                .interact()
-                .expect("Expected interaction");
-
-            *envs
-                .get_mut("OPENAPI_TITLE")
-                .expect("Expected OPENAPI_TITLE in .env") = app_name;
-
-            envs.save(location.join(".env"))
-                .expect("Expected to be able to save");
-
-            if Confirm::new()
-                .with_prompt(
-                    "Set up database now? (Requires that postgres is running & can be done later)",
-                )
-                .interact()
-                .expect("Expected interaction")
-            {
-                init_db::init_db_inner();
-            } else {
-                envs.insert("JWT_SECRET".to_owned(), random_jwt_secret());
-
-                envs.save(location.join(".env"))
-                    .expect("Expected to be able to save");
-
-                garlic_print("Make sure to setup your postgres instance");
-                garlic_print("And set your .env's DATABASE_URL manually OR run garlic init:db");
-            }
-
-            garlic_print("🧄 Done!");
-
-            println!();
-            garlic_print("Run `garlic server` to start the server!");
-            garlic_print("Run `garlic dev --open` to run and open the site!");
+        Cc::Help { .. }
+        | Cc::Info { .. }
+        | Cc::Completions { .. }
+        | Cc::Init { .. }
+        | Cc::UpdateSelf { .. } => {
+            unreachable!("Expected main to handle {command}")
         }
-        Cc::InitDb => init_db::init_db_inner(),
-        Cc::RunBackend { args } => Cmd::run("cargo run").args(args).req(),
-        Cc::UpdateSelf { args } => Cmd::run("cargo install garlic-cli").args(args).req(),
-        Cc::RunFrontend { args } => Cmd::run("bun x vite dev").app().args(args).req(),
-        Cc::Build => {
-            Cmd::run("bun x vite build").app().req();
-            Cmd::run("cargo build --release").req()
+        Cc::Log { run, limit } => run_log::print_log(project, run, limit),
+        Cc::Upgrade { to, from, template } => {
+            upgrade::upgrade(project, &template, from.as_deref(), to.as_deref())
         }
-        Cc::AddCrate {
-            name,
-            package,
-            args,
-        } => Cmd::run("cargo add")
-            .arg(name)
-            .arg("--package")
-            .arg(package)
-            .args(args)
-            .req(),
-        Cc::Prepare { args } => Cmd::run("cargo sqlx prepare --workspace").args(args).req(),
-        Cc::Preview { args } => Cmd::run("bun x vite preview").app().args(args).req(),
-        Cc::TestUnit { args } => Cmd::run("bun x vitest").app().args(args).req(),
-        Cc::TestAll => {
-            Cmd::run("cargo test").req();
-            export_fns();
-            spec_get();
-            Cmd::run("bun x vitest --run").app().req()
+        Cc::InitDb => init_db::init_db_inner(project),
+        Cc::DbSnapshot { name, force } => db_snapshot::snapshot(project, &name, force),
+        Cc::DbRestore { name } => db_snapshot::restore(project, &name),
+        Cc::DbSeed { profile, fresh } => seed::seed(project, profile.as_deref(), fresh),
+        Cc::DbSnapshots => db_snapshot::list(project),
+        Cc::DbSnapshotDelete { name } => db_snapshot::delete(project, &name),
+        Cc::Build { pipeline } => Pipeline::new(pipeline)
+            .step_after("build-frontend", &[], || project.exec("vite build").check())
+            .step_after("build-backend", &[], || {
+                project.cmd("cargo build --release").check()
+            })
+            .run(),
+        Cc::MigrateApply {
+            target_version,
+            ignore_missing,
+        } => migrate::apply(project, target_version, ignore_missing),
+        Cc::MigrateRevert {
+            target_version,
+            ignore_missing,
+        } => migrate::revert(project, target_version, ignore_missing),
+        Cc::MigrateList => migrate::list(project),
+        Cc::MigrateNew { name, simple, edit } => {
+            migrate::new_migration(project, &name, simple, edit)
         }
-        Cc::UpdateFns => export_fns(),
-        Cc::Spec => {
-            Cmd::run("cargo set-version --bump patch --package server").req();
-
-            Cmd::run("cargo test test_load_spec").req();
-            export_fns();
-            spec_get();
-            Cmd::run("bun x vitest spec --run").app().req()
+        Cc::TestAll {
+            isolated,
+            force,
+            pipeline,
+        } => Pipeline::new(pipeline)
+            .step_after("test-backend", &[], || match isolated {
+                true => test_db::test_backend_isolated(project),
+                false => project.cmd("cargo test").check(),
+            })
+            .step_after("export-fns", &[], || export_fns(project, force))
+            // `cargo test` rewrites spec.yml through test_load_spec
+            .step_after("gen-client", &["test-backend"], || spec_get(project, force))
+            .step_after("test-frontend", &["export-fns", "gen-client"], || {
+                project.exec("vitest --run").check()
+            })
+            .run(),
+        Cc::UpdateFns { force } => export_fns(project, force).unwrap_or_else(|e| e.exit()),
+        Cc::Spec { force, pipeline } => Pipeline::new(pipeline)
+            .step("bump-version", || {
+                project
+                    .cmd("cargo set-version --bump patch --package server")
+                    .check()
+            })
+            .step("test-spec", || {
+                project.cmd("cargo test test_load_spec").check()
+            })
+            .step_after("export-fns", &["bump-version"], || {
+                export_fns(project, force)
+            })
+            .step_after("gen-client", &["test-spec"], || spec_get(project, force))
+            .step_after("test-frontend", &["export-fns", "gen-client"], || {
+                project.exec("vitest spec --run").check()
+            })
+            .run(),
+        Cc::RunBackend { .. }
+        | Cc::RunFrontend { .. }
+        | Cc::AddCrate { .. }
+        | Cc::Preview { .. }
+        | Cc::TestUnit { .. } => run_wrapper(project, command),
+        Cc::Prepare { .. } | Cc::Migrate { .. } => {
+            require_sqlx_cli();
+            run_wrapper(project, command)
         }
-        Cc::Migrate { args } => Cmd::run("cargo sqlx migrate").args(args).req(),
     }
 }
 
-fn spec_get() {
-    Cmd::run("bun x openapi-zod-client ./spec.yml -o ./app/src/lib/gen/client.ts").req();
+fn run_wrapper(project: &Project, command: GarlicCommand) {
+    command
+        .wrapped_cmd(project)
+        .unwrap_or_else(|| panic!("Expected {command} to be a wrapper"))
+        .req()
 }
 
-fn export_fns() {
-    Cmd::run("wasm-pack build -d ../app/src/lib/gen/export_fns --no-pack")
-        .export_fns()
-        .req();
+fn spec_get(project: &Project, force: bool) -> Result<(), CmdError> {
+    Cache::new(project, "gen-client")
+        .input("spec.yml")
+        .output("app/src/lib/gen/client.ts")
+        .run(force, || {
+            project
+                .dlx("openapi-zod-client ../spec.yml -o ./src/lib/gen/client.ts")
+                .check()
+        })
 }
 
-fn init_inner() {
-    if !Cmd::run("bun --version").ok() {
-        error(
-            "missing_install",
-            "Bun is not installed or is not in your environment",
-        );
-    }
-    if !Cmd::run("git --version").ok() {
-        error(
-            "missing_install",
-            "Git is not installed or is not in your environment",
-        );
-    }
-    if !Cmd::run("cargo --version").ok() {
-        error(
-            "missing_install",
-            "Cargo is not installed or is not in your environment",
-        );
-    }
+fn export_fns(project: &Project, force: bool) -> Result<(), CmdError> {
+    project
+        .path_dependencies(&project.export_fns)
+        .into_iter()
+        .fold(Cache::new(project, "export-fns"), Cache::input)
+        .input("export_fns")
+        .input("Cargo.toml")
+        .input("Cargo.lock")
+        .output("app/src/lib/gen/export_fns")
+        .run(force, || {
+            Cmd::run("wasm-pack build -d ../app/src/lib/gen/export_fns --no-pack")
+                .dir(&project.export_fns)
+                .check()
+        })
+}
+
+fn init_inner(pm: PackageManager) {
+    require_tool(pm.program(), pm.name());
+    require_tool("git", "Git");
+    require_tool("cargo", "Cargo");
 
     if !Cmd::run("cargo set-version --version").ok() {
         Cmd::run("cargo install cargo-edit").opt();
     }
+}
+
+fn require_tool(program: &str, name: &str) {
+    match Cmd::new(program, ["--version"])
+        .timeout(Duration::from_secs(30))
+        .capture()
+    {
+        Ok(output) if output.success() => garlic_print(format!(
+            "Found {name} {}",
+            output.version().unwrap_or("(unknown version)")
+        )),
+        _ => error(
+            "missing_install",
+            format!("{name} is not installed or is not in your environment"),
+        ),
+    }
+}
 
+/// `prepare` and the `migrate` wrapper still need sqlx-cli, the native `migrate:*` commands don't.
+fn require_sqlx_cli() {
     if !Cmd::run("cargo sqlx --version").ok() {
-        Cmd::run("cargo install sqlx-cli --features postgres").opt();
+        Cmd::run("cargo install sqlx-cli --no-default-features --features postgres,rustls").req();
     }
 }
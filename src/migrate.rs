@@ -0,0 +1,532 @@
+use crate::{
+    dry_run, dry_run_print, error, garlic_print,
+    init_db::{connect, database_url},
+    unix_now, utc_timestamp, Cmd, PostgresUrl, Project,
+};
+use colored::Colorize;
+use postgres::Client;
+use sha2::{Digest, Sha384};
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+pub const MIGRATIONS_DIR: &str = "migrations";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MigrationKind {
+    /// `<VERSION>_<DESCRIPTION>.sql`, can't be reverted
+    Simple,
+    /// `<VERSION>_<DESCRIPTION>.up.sql`
+    ReversibleUp,
+    /// `<VERSION>_<DESCRIPTION>.down.sql`
+    ReversibleDown,
+}
+
+impl MigrationKind {
+    pub fn is_up(self) -> bool {
+        self != MigrationKind::ReversibleDown
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub description: String,
+    pub kind: MigrationKind,
+    pub sql: String,
+    pub checksum: Vec<u8>,
+}
+
+impl Migration {
+    pub fn new(version: i64, description: String, kind: MigrationKind, sql: String) -> Self {
+        Migration {
+            version,
+            description,
+            kind,
+            checksum: Sha384::digest(sql.as_bytes()).to_vec(),
+            sql,
+        }
+    }
+
+    /// sqlx runs every migration in a transaction unless it opts out with this comment
+    fn no_transaction(&self) -> bool {
+        self.sql.starts_with("-- no-transaction")
+    }
+}
+
+/// Splits a migration file name into its version, description and kind, like sqlx does.
+pub fn parse_migration_file_name(file_name: &str) -> Option<(i64, String, MigrationKind)> {
+    let (version, rest) = file_name.split_once('_')?;
+    let version = version.parse().ok()?;
+
+    let (description, kind) = if let Some(description) = rest.strip_suffix(".up.sql") {
+        (description, MigrationKind::ReversibleUp)
+    } else if let Some(description) = rest.strip_suffix(".down.sql") {
+        (description, MigrationKind::ReversibleDown)
+    } else {
+        (rest.strip_suffix(".sql")?, MigrationKind::Simple)
+    };
+
+    Some((version, description.replace('_', " "), kind))
+}
+
+/// Reads all migrations in `dir`, sorted by version.
+pub fn load_migrations(dir: impl AsRef<Path>) -> Vec<Migration> {
+    let dir = dir.as_ref();
+    let entries = fs::read_dir(dir).unwrap_or_else(|e| {
+        error(
+            "no_migrations",
+            format!("Couldn't read {}: {e}", dir.display()),
+        )
+    });
+
+    let mut migrations: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_str()?.to_owned();
+            let (version, description, kind) = parse_migration_file_name(&file_name)?;
+            let sql = fs::read_to_string(entry.path()).unwrap_or_else(|e| {
+                error("read_failed", format!("Couldn't read {file_name}: {e}"))
+            });
+
+            Some(Migration::new(version, description, kind, sql))
+        })
+        .collect();
+
+    migrations.sort_by_key(|migration| (migration.version, !migration.kind.is_up()));
+    migrations
+}
+
+/// The version of a new migration: the next number when the existing migrations are numbered
+/// sequentially, otherwise `timestamp` (`YYYYMMDDHHMMSS`) like `cargo sqlx migrate add`.
+pub fn next_version(existing: &[i64], timestamp: i64) -> i64 {
+    match existing.iter().max() {
+        Some(&max) if max < 10_000_000_000_000 => max + 1,
+        Some(&max) if max >= timestamp => max + 1,
+        _ => timestamp,
+    }
+}
+
+/// The file names `cargo sqlx migrate add` would create for this migration.
+pub fn migration_file_names(version: i64, name: &str, simple: bool) -> Vec<String> {
+    let name = name.trim().replace([' ', '-'], "_");
+    if simple {
+        vec![format!("{version}_{name}.sql")]
+    } else {
+        vec![
+            format!("{version}_{name}.up.sql"),
+            format!("{version}_{name}.down.sql"),
+        ]
+    }
+}
+
+/// Creates a new reversible migration (or a single file with `simple`) in `migrations/`.
+pub fn new_migration(project: &Project, name: &str, simple: bool, edit: bool) {
+    if name.trim().is_empty()
+        || !name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '-' | ' '))
+    {
+        error(
+            "invalid_name",
+            format!(
+                "Migration name '{name}' may only contain letters, digits, spaces, '_' and '-'"
+            ),
+        );
+    }
+
+    let dir = project.root.join(MIGRATIONS_DIR);
+    if !dry_run() {
+        fs::create_dir_all(&dir).expect("Expected to be able to create the migrations directory");
+    }
+
+    let migrations = if dir.is_dir() {
+        load_migrations(&dir)
+    } else {
+        vec![]
+    };
+    let description = name.trim().replace(['_', '-'], " ");
+    if let Some(existing) = migrations
+        .iter()
+        .find(|migration| migration.description == description)
+    {
+        error(
+            "migration_exists",
+            format!(
+                "Migration '{description}' already exists as version {}",
+                existing.version
+            ),
+        );
+    }
+
+    let versions: Vec<_> = migrations
+        .iter()
+        .map(|migration| migration.version)
+        .collect();
+    let version = next_version(&versions, utc_timestamp(unix_now()));
+
+    let paths: Vec<_> = migration_file_names(version, name, simple)
+        .into_iter()
+        .map(|file_name| dir.join(file_name))
+        .collect();
+
+    for path in &paths {
+        let content = if path.to_string_lossy().ends_with(".down.sql") {
+            format!("-- Reverts '{description}'\n")
+        } else {
+            format!("-- Add migration script here ('{description}')\n")
+        };
+
+        if dry_run() {
+            dry_run_print(format!("Would create {}", path.display()));
+            continue;
+        }
+
+        fs::write(path, content).expect("Expected to be able to write migration");
+        garlic_print(format!(
+            "Created {MIGRATIONS_DIR}/{}",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+    }
+
+    if edit {
+        match env::var("VISUAL").or_else(|_| env::var("EDITOR")) {
+            Ok(editor) if !editor.trim().is_empty() => Cmd::run(editor.trim())
+                .args(paths.iter().map(|path| path.to_string_lossy().into_owned()))
+                .req(),
+            _ => error("no_editor", "Set $EDITOR to open new migrations"),
+        }
+    }
+}
+
+/// The advisory lock sqlx takes while migrating, so garlic and sqlx never migrate at once.
+pub fn lock_id(database: &str) -> i64 {
+    0x3d32ad9e * crc32(database.as_bytes()) as i64
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Applies migrations the way `cargo sqlx migrate` does, sharing its `_sqlx_migrations`
+/// table so both tools can be used on the same database.
+struct Migrator {
+    client: Client,
+    database: String,
+    migrations: Vec<Migration>,
+}
+
+impl Migrator {
+    fn open(project: &Project, url: &PostgresUrl) -> Self {
+        let root = &project.root;
+        let mut client = connect(url);
+
+        if dry_run() {
+            return Migrator {
+                client,
+                migrations: load_migrations(root.join(MIGRATIONS_DIR)),
+                database: url.database.clone(),
+            };
+        }
+
+        client
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS _sqlx_migrations (
+                    version BIGINT PRIMARY KEY,
+                    description TEXT NOT NULL,
+                    installed_on TIMESTAMPTZ NOT NULL DEFAULT now(),
+                    success BOOLEAN NOT NULL,
+                    checksum BYTEA NOT NULL,
+                    execution_time BIGINT NOT NULL
+                );",
+            )
+            .unwrap_or_else(|e| error("migrate_failed", e));
+
+        client
+            .execute("SELECT pg_advisory_lock($1)", &[&lock_id(&url.database)])
+            .unwrap_or_else(|e| error("migrate_failed", e));
+
+        Migrator {
+            client,
+            migrations: load_migrations(root.join(MIGRATIONS_DIR)),
+            database: url.database.clone(),
+        }
+    }
+
+    /// Checksums of the applied migrations by version
+    fn applied(&mut self) -> HashMap<i64, Vec<u8>> {
+        let exists = self
+            .client
+            .query_one("SELECT to_regclass('_sqlx_migrations') IS NOT NULL", &[])
+            .unwrap_or_else(|e| error("migrate_failed", e));
+        if !exists.get::<_, bool>(0) {
+            return HashMap::new();
+        }
+
+        let dirty = self
+            .client
+            .query_opt(
+                "SELECT version FROM _sqlx_migrations WHERE success = false ORDER BY version LIMIT 1",
+                &[],
+            )
+            .unwrap_or_else(|e| error("migrate_failed", e));
+
+        if let Some(row) = dirty {
+            let version: i64 = row.get(0);
+            error(
+                "dirty_migration",
+                format!("Migration {version} was partially applied, fix it manually"),
+            );
+        }
+
+        self.client
+            .query("SELECT version, checksum FROM _sqlx_migrations", &[])
+            .unwrap_or_else(|e| error("migrate_failed", e))
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect()
+    }
+
+    fn validate(&self, applied: &HashMap<i64, Vec<u8>>, ignore_missing: bool) {
+        for (version, checksum) in applied {
+            match self
+                .migrations
+                .iter()
+                .find(|migration| migration.kind.is_up() && migration.version == *version)
+            {
+                Some(migration) if migration.checksum != *checksum => error(
+                    "checksum_mismatch",
+                    format!(
+                        "Migration {version} was changed after it was applied to {}",
+                        self.database
+                    ),
+                ),
+                None if !ignore_missing => error(
+                    "missing_migration",
+                    format!(
+                        "Migration {version} was applied but is missing in {MIGRATIONS_DIR}/. Use --ignore-missing to continue"
+                    ),
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    fn run(&mut self, migration: &Migration, up: bool) -> Duration {
+        let start = Instant::now();
+        if dry_run() {
+            return Duration::ZERO;
+        }
+
+        let result = if migration.no_transaction() {
+            self.client.batch_execute(&migration.sql).and_then(|_| {
+                record(&mut self.client, migration, up)?;
+                Ok(())
+            })
+        } else {
+            self.client.transaction().and_then(|mut transaction| {
+                transaction.batch_execute(&migration.sql)?;
+                record(&mut transaction, migration, up)?;
+                transaction.commit()
+            })
+        };
+
+        if let Err(e) = result {
+            error(
+                "migrate_failed",
+                format!(
+                    "Migration {} ({}) failed: {e}",
+                    migration.version, migration.description
+                ),
+            );
+        }
+
+        let elapsed = start.elapsed();
+        if up {
+            self.client
+                .execute(
+                    "UPDATE _sqlx_migrations SET execution_time = $1 WHERE version = $2",
+                    &[&(elapsed.as_nanos() as i64), &migration.version],
+                )
+                .unwrap_or_else(|e| error("migrate_failed", e));
+        }
+
+        elapsed
+    }
+}
+
+fn record(
+    client: &mut impl postgres::GenericClient,
+    migration: &Migration,
+    up: bool,
+) -> Result<u64, postgres::Error> {
+    if up {
+        client.execute(
+            "INSERT INTO _sqlx_migrations ( version, description, success, checksum, execution_time )
+             VALUES ( $1, $2, TRUE, $3, -1 )",
+            &[&migration.version, &migration.description, &migration.checksum],
+        )
+    } else {
+        client.execute(
+            "DELETE FROM _sqlx_migrations WHERE version = $1",
+            &[&migration.version],
+        )
+    }
+}
+
+/// Applies all pending migrations, up to and including `target` if set.
+pub fn apply(project: &Project, target: Option<i64>, ignore_missing: bool) {
+    apply_to(
+        project,
+        &database_url(&project.root),
+        target,
+        ignore_missing,
+    );
+}
+
+/// [`apply`] for the database at `url` instead of the one in `.env`
+pub fn apply_to(project: &Project, url: &PostgresUrl, target: Option<i64>, ignore_missing: bool) {
+    let mut migrator = Migrator::open(project, url);
+    let applied = migrator.applied();
+    migrator.validate(&applied, ignore_missing);
+
+    let pending: Vec<_> = migrator
+        .migrations
+        .iter()
+        .filter(|migration| migration.kind.is_up())
+        .filter(|migration| !applied.contains_key(&migration.version))
+        .filter(|migration| target.is_none_or(|target| migration.version <= target))
+        .cloned()
+        .collect();
+
+    if pending.is_empty() {
+        garlic_print(format!("{} is up to date", migrator.database));
+        return;
+    }
+
+    for migration in &pending {
+        if dry_run() {
+            dry_run_print(format!(
+                "Would apply {}/{} {}",
+                migration.version,
+                "migrate".green(),
+                migration.description
+            ));
+            continue;
+        }
+
+        let elapsed = migrator.run(migration, true);
+        garlic_print(format!(
+            "Applied {}/{} {} ({elapsed:?})",
+            migration.version,
+            "migrate".green(),
+            migration.description
+        ));
+    }
+}
+
+/// Reverts the last applied migration, or every migration after `target` if set.
+pub fn revert(project: &Project, target: Option<i64>, ignore_missing: bool) {
+    let mut migrator = Migrator::open(project, &database_url(&project.root));
+    let applied = migrator.applied();
+    migrator.validate(&applied, ignore_missing);
+
+    let mut to_revert: Vec<_> = migrator
+        .migrations
+        .iter()
+        .filter(|migration| migration.kind == MigrationKind::ReversibleDown)
+        .filter(|migration| applied.contains_key(&migration.version))
+        .filter(|migration| target.is_none_or(|target| migration.version > target))
+        .cloned()
+        .collect();
+    to_revert.reverse();
+
+    if target.is_none() {
+        to_revert.truncate(1);
+    }
+
+    if to_revert.is_empty() {
+        garlic_print("No migrations to revert");
+        return;
+    }
+
+    for migration in &to_revert {
+        if dry_run() {
+            dry_run_print(format!(
+                "Would apply {}/{} {}",
+                migration.version,
+                "revert".yellow(),
+                migration.description
+            ));
+            continue;
+        }
+
+        let elapsed = migrator.run(migration, false);
+        garlic_print(format!(
+            "Applied {}/{} {} ({elapsed:?})",
+            migration.version,
+            "revert".yellow(),
+            migration.description
+        ));
+    }
+}
+
+/// Prints every migration and whether it's applied to the database.
+pub fn list(project: &Project) {
+    let mut migrator = Migrator::open(project, &database_url(&project.root));
+    let applied = migrator.applied();
+
+    for migration in migrator
+        .migrations
+        .iter()
+        .filter(|migration| migration.kind.is_up())
+    {
+        let status = match applied.get(&migration.version) {
+            Some(checksum) if *checksum != migration.checksum => {
+                "installed (different checksum)".red()
+            }
+            Some(_) => "installed".green(),
+            None => "pending".yellow(),
+        };
+
+        println!(
+            "{}/{status} {}",
+            migration.version.to_string().blue(),
+            migration.description
+        );
+    }
+
+    let mut missing: Vec<_> = applied
+        .keys()
+        .filter(|version| {
+            !migrator
+                .migrations
+                .iter()
+                .any(|migration| migration.version == **version)
+        })
+        .collect();
+    missing.sort();
+
+    for version in missing {
+        println!(
+            "{}/{} (not in {MIGRATIONS_DIR}/)",
+            version.to_string().blue(),
+            "installed".red()
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{
+    dry_run, dry_run_print, error, garlic_print,
+    init_db::{connect, database_url, quote_ident},
+    Project,
+};
+use colored::Colorize;
+use postgres::Transaction;
+use sha2::{Digest, Sha384};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const SEEDS_DIR: &str = "seeds";
+
+/// Tables that keep track of the database itself and are never truncated
+const BOOKKEEPING_TABLES: [&str; 2] = ["_sqlx_migrations", "_garlic_seeds"];
+
+#[derive(Debug, PartialEq)]
+pub struct SeedFile {
+    /// The path relative to `seeds/`, e.g. `01_users.sql` or `dev/02_posts.sql`
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// The `.sql` files in `dir` and, if set, in `dir/<profile>`, ordered by file name.
+/// Profile seeds run after the shared seed with the same file name.
+pub fn seed_files(dir: impl AsRef<Path>, profile: Option<&str>) -> Vec<SeedFile> {
+    let dir = dir.as_ref();
+    let mut files: Vec<_> = sql_files(dir)
+        .into_iter()
+        .map(|file_name| (file_name, None))
+        .collect();
+
+    if let Some(profile) = profile {
+        let profile_dir = dir.join(profile);
+        if !profile_dir.is_dir() {
+            error(
+                "no_profile",
+                format!("Seed profile '{profile}' not found in {SEEDS_DIR}/"),
+            );
+        }
+
+        files.extend(
+            sql_files(&profile_dir)
+                .into_iter()
+                .map(|file_name| (file_name, Some(profile))),
+        );
+    }
+
+    files.sort_by(|(a, a_profile), (b, b_profile)| {
+        a.cmp(b).then(a_profile.is_some().cmp(&b_profile.is_some()))
+    });
+
+    files
+        .into_iter()
+        .map(|(file_name, profile)| match profile {
+            Some(profile) => SeedFile {
+                name: format!("{profile}/{file_name}"),
+                path: dir.join(profile).join(file_name),
+            },
+            None => SeedFile {
+                path: dir.join(&file_name),
+                name: file_name,
+            },
+        })
+        .collect()
+}
+
+fn sql_files(dir: &Path) -> Vec<String> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_file())
+                .filter_map(|entry| entry.file_name().to_str().map(str::to_owned))
+                .filter(|file_name| file_name.ends_with(".sql"))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Runs the seeds that haven't run yet in a single transaction.
+/// With `fresh`, all tables are truncated first and every seed runs again.
+pub fn seed(project: &Project, profile: Option<&str>, fresh: bool) {
+    let root = &project.root;
+    let dir = root.join(SEEDS_DIR);
+    if !dir.is_dir() {
+        error(
+            "no_seeds",
+            format!("No {SEEDS_DIR}/ directory found in {}", root.display()),
+        );
+    }
+
+    let files = seed_files(&dir, profile);
+    let url = database_url(root);
+    let mut client = connect(&url);
+
+    let result = client.transaction().and_then(|mut transaction| {
+        transaction.batch_execute(
+            "CREATE TABLE IF NOT EXISTS _garlic_seeds (
+                name TEXT PRIMARY KEY,
+                checksum BYTEA NOT NULL,
+                ran_on TIMESTAMPTZ NOT NULL DEFAULT now()
+            );",
+        )?;
+
+        if fresh && dry_run() {
+            dry_run_print("Would truncate all tables");
+        } else if fresh {
+            truncate_all(&mut transaction)?;
+        }
+
+        let ran: HashMap<String, Vec<u8>> = if fresh && dry_run() {
+            HashMap::new()
+        } else {
+            transaction
+                .query("SELECT name, checksum FROM _garlic_seeds", &[])?
+                .into_iter()
+                .map(|row| (row.get(0), row.get(1)))
+                .collect()
+        };
+
+        let mut count = 0;
+        for file in &files {
+            let sql = fs::read_to_string(&file.path).unwrap_or_else(|e| {
+                error("read_failed", format!("Couldn't read {}: {e}", file.name))
+            });
+            let checksum = Sha384::digest(sql.as_bytes()).to_vec();
+
+            match ran.get(&file.name) {
+                Some(ran_checksum) if *ran_checksum != checksum => {
+                    garlic_print(format!(
+                        "Skipping {}, it changed since it ran. Use --fresh to run it again",
+                        file.name.yellow()
+                    ));
+                    continue;
+                }
+                Some(_) => continue,
+                None => {}
+            }
+
+            if dry_run() {
+                dry_run_print(format!("Would seed {}", file.name.cyan()));
+                count += 1;
+                continue;
+            }
+
+            garlic_print(format!("Seeding {}", file.name.cyan()));
+            if let Err(e) = transaction.batch_execute(&sql) {
+                error(
+                    "seed_failed",
+                    format!("Seed {} failed, nothing was seeded: {e}", file.name),
+                );
+            }
+
+            transaction.execute(
+                "INSERT INTO _garlic_seeds (name, checksum) VALUES ($1, $2)",
+                &[&file.name, &checksum],
+            )?;
+            count += 1;
+        }
+
+        // Dropping the transaction rolls back the bookkeeping table
+        if !dry_run() {
+            transaction.commit()?;
+        }
+        Ok(count)
+    });
+
+    match result {
+        Ok(0) => garlic_print(format!("{} is already seeded", url.database)),
+        Ok(_) if dry_run() => {}
+        Ok(count) => garlic_print(format!("Ran {count} seed(s) on {}", url.database)),
+        Err(e) => error("seed_failed", e),
+    }
+}
+
+fn truncate_all(transaction: &mut Transaction) -> Result<(), postgres::Error> {
+    let tables: Vec<String> = transaction
+        .query(
+            "SELECT tablename FROM pg_tables WHERE schemaname = current_schema()",
+            &[],
+        )?
+        .into_iter()
+        .map(|row| row.get(0))
+        .filter(|table: &String| !BOOKKEEPING_TABLES.contains(&table.as_str()))
+        .collect();
+
+    transaction.batch_execute("DELETE FROM _garlic_seeds")?;
+    if tables.is_empty() {
+        return Ok(());
+    }
+
+    garlic_print(format!("Truncating {} table(s)", tables.len()));
+    let tables: Vec<_> = tables.iter().map(|table| quote_ident(table)).collect();
+    transaction.batch_execute(&format!(
+        "TRUNCATE TABLE {} RESTART IDENTITY CASCADE",
+        tables.join(", ")
+    ))
+}
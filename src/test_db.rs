@@ -0,0 +1,125 @@
+use crate::{
+    defer_interrupts, dry_run, dry_run_print, error, error_opt, garlic_print,
+    init_db::{self, connect, database_url, quote_ident, MAX_IDENT_LEN},
+    interrupted, migrate, on_exit, CmdError, ExitHook, PostgresUrl, Project, Stopped,
+};
+use postgres::{Client, NoTls};
+use rand::{distr::Alphanumeric, rng, Rng};
+
+/// A uniquely named database on the configured server, dropped again when this goes out of scope
+/// or garlic exits on an error.
+pub struct IsolatedDatabase {
+    admin: PostgresUrl,
+    pub url: PostgresUrl,
+    _exit_hook: Option<ExitHook>,
+}
+
+impl IsolatedDatabase {
+    pub fn create(project: &Project) -> Self {
+        let url = database_url(&project.root);
+        let admin = url.with_database("postgres");
+
+        let suffix: String = rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(|ch| char::from(ch).to_ascii_lowercase())
+            .collect();
+        let name = isolated_database_name(&url.database, &suffix);
+
+        if dry_run() {
+            dry_run_print(format!("Would create isolated database {name}"));
+            return IsolatedDatabase {
+                url: url.with_database(name),
+                admin,
+                _exit_hook: None,
+            };
+        }
+
+        connect(&admin)
+            .batch_execute(&format!("CREATE DATABASE {}", quote_ident(&name)))
+            .unwrap_or_else(|e| error("create_failed", format!("Couldn't create {name}: {e}")));
+
+        garlic_print(format!("Created isolated database {name}"));
+
+        let exit_hook = {
+            let (admin, name) = (admin.clone(), name.clone());
+            on_exit(move || drop_database(&admin, &name))
+        };
+
+        IsolatedDatabase {
+            url: url.with_database(name),
+            admin,
+            _exit_hook: Some(exit_hook),
+        }
+    }
+}
+
+impl Drop for IsolatedDatabase {
+    fn drop(&mut self) {
+        let name = &self.url.database;
+        if dry_run() {
+            dry_run_print(format!("Would drop isolated database {name}"));
+            return;
+        }
+
+        drop_database(&self.admin, name);
+    }
+}
+
+fn drop_database(admin: &PostgresUrl, name: &str) {
+    let result = Client::connect(&admin.to_database_connection_params(), NoTls)
+        .and_then(|mut client| init_db::drop_database(&mut client, name));
+
+    match result {
+        Ok(_) => garlic_print(format!("Dropped isolated database {name}")),
+        Err(e) => error_opt(
+            "drop_failed",
+            format!("Couldn't drop isolated database {name}, drop it manually: {e}"),
+        ),
+    }
+}
+
+/// `<DATABASE>__test_<SUFFIX>`, shortening the original name to stay within postgres' limit
+pub fn isolated_database_name(database: &str, suffix: &str) -> String {
+    let tail = format!("__test_{suffix}");
+    let prefix: String = database
+        .chars()
+        .take(MAX_IDENT_LEN.saturating_sub(tail.len()))
+        .collect();
+
+    format!("{prefix}{tail}")
+}
+
+/// Runs the backend tests against a freshly migrated database that is dropped afterwards,
+/// also when the tests fail or are interrupted with Ctrl-C.
+pub fn test_backend_isolated(project: &Project) -> Result<(), CmdError> {
+    // Ctrl-C still stops the tests, garlic only survives it to clean up
+    defer_interrupts();
+
+    let database = IsolatedDatabase::create(project);
+    let url = database.url.to_env_connection_string();
+
+    // The database doesn't exist with --dry-run
+    match dry_run() {
+        true => dry_run_print(format!(
+            "Would apply the migrations to {}",
+            database.url.database
+        )),
+        false => migrate::apply_to(project, &database.url, None, false),
+    }
+
+    let result = match interrupted() {
+        true => Ok(()),
+        false => project.cmd("cargo test").env("DATABASE_URL", &url).check(),
+    };
+
+    drop(database);
+
+    match result {
+        Ok(()) | Err(CmdError::Failed { .. }) if interrupted() => Err(CmdError::Stopped {
+            command: "cargo test".to_owned(),
+            stopped: Stopped::Interrupted,
+        }),
+        result => result,
+    }
+}
@@ -1,7 +1,37 @@
-use clap::{CommandFactory, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::{engine::complete, env::Shells};
+use std::{
+    fs,
+    num::NonZeroUsize,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 use strum::IntoEnumIterator;
+use tempdir::TempDir;
 
-use crate::{GarlicCommand, GarlicParser, PostgresUrl};
+use crate::{
+    buffer_output,
+    completions::{completion_script, Shell},
+    db_snapshot::{snapshot_database_name, Snapshot, Snapshots},
+    edit_distance, fingerprint, format_duration, garlic_print, garlic_version,
+    info::{mask, masked_env},
+    migrate::{
+        lock_id, migration_file_names, next_version, parse_migration_file_name, MigrationKind,
+    },
+    parse_version, render_docs,
+    run_log::LogRecord,
+    seed::seed_files,
+    self_update::{
+        changelog_range, index_path, parse_index, parse_tags, select_release, UpdateSource,
+    },
+    shell_words::{join, split, SplitError},
+    suggest_commands, take_output,
+    test_db::isolated_database_name,
+    upgrade::{changed_files, plan, Action},
+    utc_timestamp, wrap, Cache, Category, Cmd, CmdError, DotEnvs, GarlicCommand, GarlicParser,
+    HelpFormat, Manifest, PackageManager, Pipeline, PipelineArgs, PostgresUrl, Project, StepStatus,
+    Stopped, Suggestion, MANIFEST_FILE,
+};
 
 #[test]
 fn test_parse_postgres_url() {
@@ -79,3 +109,707 @@ fn test_parse_postgres_url_without_pwd() {
         }
     );
 }
+
+#[test]
+fn test_snapshots_file_roundtrip() {
+    let snapshots = Snapshots(vec![Snapshot {
+        name: "seeded".to_owned(),
+        database: "DB__snap_seeded".to_owned(),
+        source: "DB".to_owned(),
+        created: 1700000000,
+    }]);
+
+    assert_eq!(Snapshots::parse(&snapshots.to_file_string()), snapshots);
+}
+
+#[test]
+fn test_snapshot_database_name() {
+    assert_eq!(
+        snapshot_database_name("DB", "before-bug").as_deref(),
+        Ok("DB__snap_before-bug")
+    );
+    assert!(snapshot_database_name("DB", "no spaces").is_err());
+    assert!(snapshot_database_name("DB", &"a".repeat(60)).is_err());
+}
+
+#[test]
+fn test_parse_migration_file_name() {
+    assert_eq!(
+        parse_migration_file_name("20240101000000_create_users.up.sql"),
+        Some((
+            20240101000000,
+            "create users".to_owned(),
+            MigrationKind::ReversibleUp
+        ))
+    );
+    assert_eq!(
+        parse_migration_file_name("1_init.sql"),
+        Some((1, "init".to_owned(), MigrationKind::Simple))
+    );
+    assert_eq!(parse_migration_file_name("README.md"), None);
+}
+
+#[test]
+fn test_migration_lock_id_matches_sqlx() {
+    // crc32("123456789") is the standard check value 0xcbf43926
+    assert_eq!(lock_id("123456789"), 0x3d32ad9e * 0xcbf43926);
+}
+
+#[test]
+fn test_new_migration_version() {
+    assert_eq!(utc_timestamp(0), 19700101000000);
+    assert_eq!(utc_timestamp(1709210096), 20240229123456);
+
+    assert_eq!(next_version(&[], 20240229123456), 20240229123456);
+    assert_eq!(next_version(&[1, 2], 20240229123456), 3);
+    assert_eq!(
+        next_version(&[20240229123456], 20240229123456),
+        20240229123457
+    );
+    assert_eq!(
+        migration_file_names(3, "create users", false),
+        vec!["3_create_users.up.sql", "3_create_users.down.sql"]
+    );
+}
+
+#[test]
+fn test_seed_files_order() {
+    let seeds = TempDir::new("garlic-seeds").unwrap();
+    fs::create_dir(seeds.path().join("dev")).unwrap();
+    for file in [
+        "02_posts.sql",
+        "01_users.sql",
+        "README.md",
+        "dev/01_users.sql",
+    ] {
+        fs::write(seeds.path().join(file), "").unwrap();
+    }
+
+    let names = |profile| -> Vec<_> {
+        seed_files(seeds.path(), profile)
+            .into_iter()
+            .map(|file| file.name)
+            .collect()
+    };
+
+    assert_eq!(names(None), vec!["01_users.sql", "02_posts.sql"]);
+    assert_eq!(
+        names(Some("dev")),
+        vec!["01_users.sql", "dev/01_users.sql", "02_posts.sql"]
+    );
+}
+
+#[test]
+fn test_isolated_database_name() {
+    assert_eq!(isolated_database_name("DB", "abc123"), "DB__test_abc123");
+    assert_eq!(isolated_database_name(&"a".repeat(70), "abc123").len(), 63);
+}
+
+#[test]
+fn test_split_command() {
+    assert_eq!(
+        split("cargo  test -- 'a b' \"c \\\"d\\\"\" e\\ f").unwrap(),
+        vec!["cargo", "test", "--", "a b", "c \"d\"", "e f"]
+    );
+    assert_eq!(split("echo ''").unwrap(), vec!["echo", ""]);
+    assert_eq!(split("echo 'a"), Err(SplitError::UnterminatedQuote('\'')));
+    assert_eq!(split("echo a\\"), Err(SplitError::TrailingBackslash));
+}
+
+#[test]
+fn test_join_command() {
+    let words = ["cargo", "run", "--", "it's", "a b", "", "--x=1"];
+    let line = join(words);
+
+    assert_eq!(line, "cargo run -- 'it'\\''s' 'a b' '' --x=1");
+    assert_eq!(split(&line).unwrap(), words);
+}
+
+#[test]
+fn test_parse_tool_version() {
+    assert_eq!(
+        parse_version("cargo 1.85.0 (d73d2caf9 2024-12-31)"),
+        Some("1.85.0")
+    );
+    assert_eq!(parse_version("git version 2.43.0\n"), Some("2.43.0"));
+    assert_eq!(parse_version("v22.11.0"), Some("22.11.0"));
+    assert_eq!(parse_version("no version here"), None);
+}
+
+#[test]
+fn test_cmd_capture() {
+    let output = Cmd::new("sh", ["-c", "echo out; echo err >&2; exit 3"])
+        .capture()
+        .unwrap();
+
+    assert_eq!(output.stdout, "out\n");
+    assert_eq!(output.stderr, "err\n");
+    assert_eq!(output.code, Some(3));
+    assert!(!output.success());
+}
+
+#[test]
+fn test_cmd_timeout() {
+    let output = Cmd::new("sleep", ["10"])
+        .timeout(Duration::from_millis(100))
+        .capture()
+        .unwrap();
+
+    assert_eq!(
+        output.stopped,
+        Some(Stopped::TimedOut(Duration::from_millis(100)))
+    );
+    assert!(output.duration < Duration::from_secs(5));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_stops_orphaned_grandchildren() {
+    let output = Cmd::new("sh", ["-c", "sleep 30 >/dev/null & echo $!"])
+        .capture()
+        .unwrap();
+    let grandchild = output.stdout.trim();
+
+    let alive = Cmd::new("kill", ["-0", grandchild]).capture().unwrap();
+    assert!(
+        !alive.success(),
+        "Expected sleep {grandchild} to be stopped"
+    );
+}
+
+#[test]
+fn test_pipeline_steps() {
+    let failing = || {
+        Err(CmdError::Failed {
+            command: "false".to_owned(),
+            code: Some(2),
+        })
+    };
+    let pipeline = |args| {
+        Pipeline::new(args)
+            .step("first", || Ok(()))
+            .step("second", failing)
+            .step("third", || Ok(()))
+    };
+    let statuses = |args| -> Vec<_> {
+        pipeline(args)
+            .run_steps()
+            .into_iter()
+            .map(|(name, status)| match status {
+                StepStatus::Passed(_) => format!("{name}: passed"),
+                StepStatus::Failed(_, e) => format!("{name}: failed {}", e.exit_code()),
+                StepStatus::Skipped => format!("{name}: skipped"),
+                StepStatus::NotRun => format!("{name}: not run"),
+            })
+            .collect()
+    };
+
+    assert_eq!(
+        statuses(PipelineArgs::default()),
+        vec!["first: passed", "second: failed 2", "third: not run"]
+    );
+    assert_eq!(
+        statuses(PipelineArgs {
+            continue_on_error: true,
+            ..Default::default()
+        }),
+        vec!["first: passed", "second: failed 2", "third: passed"]
+    );
+    assert_eq!(
+        statuses(PipelineArgs {
+            from: Some("third".to_owned()),
+            ..Default::default()
+        }),
+        vec!["first: skipped", "second: skipped", "third: passed"]
+    );
+    assert_eq!(
+        statuses(PipelineArgs {
+            only: vec!["first".to_owned()],
+            ..Default::default()
+        }),
+        vec!["first: passed", "second: skipped", "third: skipped"]
+    );
+}
+
+#[test]
+fn test_format_duration() {
+    assert_eq!(format_duration(Duration::from_millis(1234)), "1.2s");
+    assert_eq!(format_duration(Duration::from_secs(125)), "2m 05s");
+}
+
+#[test]
+fn test_pipeline_runs_independent_steps_at_once() {
+    let start = Instant::now();
+    let statuses = Pipeline::new(PipelineArgs {
+        jobs: NonZeroUsize::new(2),
+        ..Default::default()
+    })
+    .step_after("first", &[], || Cmd::new("sleep", ["0.5"]).check())
+    .step_after("second", &[], || Cmd::new("sleep", ["0.5"]).check())
+    .step("third", || Ok(()))
+    .run_steps();
+
+    assert!(start.elapsed() < Duration::from_millis(900));
+    assert!(statuses
+        .iter()
+        .all(|(_, status)| matches!(status, StepStatus::Passed(_))));
+}
+
+#[test]
+fn test_buffered_output() {
+    buffer_output();
+    garlic_print("hello");
+    Cmd::new("sh", ["-c", "echo out; echo err >&2"])
+        .check()
+        .unwrap();
+    let output = take_output();
+
+    assert!(output.contains("hello"));
+    assert!(output.contains("out\nerr\n"));
+}
+
+#[test]
+fn test_fingerprint() {
+    let root = TempDir::new("garlic-cache").unwrap();
+    let root = root.path();
+    fs::create_dir_all(root.join("export_fns/src")).unwrap();
+    fs::create_dir_all(root.join("export_fns/target")).unwrap();
+    fs::write(root.join("export_fns/src/lib.rs"), "fn a() {}").unwrap();
+
+    let inputs = [PathBuf::from("export_fns")];
+    let before = fingerprint(root, &inputs);
+
+    fs::write(root.join("export_fns/target/out.wasm"), "ignored").unwrap();
+    assert_eq!(fingerprint(root, &inputs), before);
+
+    fs::write(root.join("export_fns/src/lib.rs"), "fn b() {}").unwrap();
+    assert_ne!(fingerprint(root, &inputs), before);
+}
+
+#[test]
+fn test_fingerprint_ignores_workspace_versions() {
+    let root = TempDir::new("garlic-cache").unwrap();
+    let root = root.path();
+    let lock = |server: &str, serde: &str| {
+        let lock = format!(
+            "version = 4\n\n\
+            [[package]]\nname = \"serde\"\nversion = \"{serde}\"\n\
+            source = \"registry+https://github.com/rust-lang/crates.io-index\"\n\n\
+            [[package]]\nname = \"server\"\nversion = \"{server}\"\n\
+            dependencies = [\n \"serde\",\n]\n"
+        );
+        fs::write(root.join("Cargo.lock"), lock).unwrap();
+        fingerprint(root, &[PathBuf::from("Cargo.lock")])
+    };
+
+    let before = lock("0.1.0", "1.0.0");
+    assert_eq!(lock("0.1.1", "1.0.0"), before);
+    assert_ne!(lock("0.1.1", "1.0.1"), before);
+}
+
+#[test]
+fn test_cache_ignores_inputs_changed_while_running() {
+    let root = TempDir::new("garlic-cache").unwrap();
+    let project = Project::at(root.path());
+    fs::write(root.path().join("spec.yml"), "old").unwrap();
+
+    let runs = std::cell::Cell::new(0);
+    let run = |write: Option<&str>| {
+        Cache::new(&project, "gen-client")
+            .input("spec.yml")
+            .run(false, || {
+                runs.set(runs.get() + 1);
+                if let Some(content) = write {
+                    fs::write(root.path().join("spec.yml"), content).unwrap();
+                }
+                Ok(())
+            })
+            .unwrap();
+    };
+
+    // Rewritten by another step while this one ran
+    run(Some("new"));
+    run(None);
+    assert_eq!(runs.get(), 2);
+
+    run(None);
+    assert_eq!(runs.get(), 2);
+}
+
+#[test]
+fn test_parse_log_record() {
+    let log = "command\tgarlic spec\n\
+        started\t1709210096\n\
+        directory\t/project\n\
+        run\ttest-spec\t/project\t101\t1500\tcargo test test_load_spec\n\
+        step\ttest-spec\tfailed\t1500\n\
+        output\ttest-spec\t14\nline 1\nline 2\n\n\
+        step\tgen-client\tnot run\t-\n\
+        error\tfailed\t\"cargo test test_load_spec\" exited with code 101\n\
+        exit\t101\t1600\n";
+    let record = LogRecord::parse(log);
+
+    assert_eq!(record.command, "garlic spec");
+    assert_eq!(record.started, 1709210096);
+    assert_eq!(record.runs[0].step.as_deref(), Some("test-spec"));
+    assert_eq!(record.runs[0].result, "101");
+    assert_eq!(record.runs[0].duration, Duration::from_millis(1500));
+    assert_eq!(
+        record.outputs,
+        vec![("test-spec".to_owned(), "line 1\nline 2\n".to_owned())]
+    );
+    assert_eq!(record.steps.len(), 2);
+    assert_eq!(record.steps[1].duration, None);
+    assert_eq!(record.errors[0].0, "failed");
+    assert_eq!(record.exit, Some((101, Duration::from_millis(1600))));
+}
+
+#[test]
+fn test_find_project() {
+    let root = TempDir::new("garlic-project").unwrap();
+    let root = root.path();
+    fs::create_dir_all(root.join("app/src")).unwrap();
+    fs::create_dir(root.join(".garlic")).unwrap();
+
+    let project = Project::find(Some(&root.join("app/src"))).expect("Expected a project");
+    assert_eq!(project, Project::at(root));
+    assert_eq!(project.app, root.join("app"));
+    assert_eq!(Project::find(Some(&root.join("app/missing"))), None);
+
+    let args = |line: &str| Project::path_arg(line.split(' ').map(str::to_owned));
+    assert_eq!(args("-C app preview"), Some(PathBuf::from("app")));
+    assert_eq!(args("preview --project=app"), Some(PathBuf::from("app")));
+    assert_eq!(args("-Capp preview"), Some(PathBuf::from("app")));
+    assert_eq!(args("preview --help"), None);
+
+    let outside = TempDir::new("garlic-outside").unwrap();
+    assert_eq!(Project::find(Some(outside.path())), None);
+}
+
+#[test]
+fn test_manifest_package_manager() {
+    assert_eq!(Manifest::parse("").unwrap(), Manifest::default());
+
+    let manifest = Manifest::parse("package-manager = \"pnpm\"\n").unwrap();
+    assert_eq!(manifest.package_manager, Some(PackageManager::Pnpm));
+    assert_eq!(
+        Manifest::parse(&manifest.to_file_string()).unwrap(),
+        manifest
+    );
+    assert!(Manifest::parse("package-manager = \"cargo\"\n").is_err());
+
+    let root = TempDir::new("garlic-manifest").unwrap();
+    fs::create_dir(root.path().join(".garlic")).unwrap();
+    manifest.save(root.path());
+
+    let project = Project::at(root.path());
+    assert_eq!(project.package_manager, PackageManager::Pnpm);
+    let project = project.with_package_manager(Some(PackageManager::Npm));
+    assert_eq!(project.package_manager.exec(), "npx");
+    assert_eq!(project.package_manager.install(), "npm install");
+    assert_eq!(project.package_manager.dlx(), "npx --yes");
+    assert_eq!(project.package_manager.run(), "npm run");
+    assert_eq!(PackageManager::Pnpm.dlx(), "pnpm dlx");
+}
+
+#[test]
+fn test_dotgarlic_keeps_anchor_file() {
+    let root = TempDir::new("garlic-anchor").unwrap();
+    fs::write(root.path().join(".garlic"), "").unwrap();
+
+    Manifest::new(PackageManager::Yarn, None).save(root.path());
+    assert!(root.path().join(".garlic").is_file());
+    assert!(root.path().join(".garlic.d").join(MANIFEST_FILE).is_file());
+    assert!(root.path().join(".garlic.d").join(".gitignore").is_file());
+    assert_eq!(
+        Project::at(root.path()).package_manager,
+        PackageManager::Yarn
+    );
+}
+
+#[test]
+fn test_manifest_garlic_version() {
+    let version = |version: &str| semver::Version::parse(version).unwrap();
+
+    let manifest = Manifest::new(PackageManager::Bun, Some("abc123".to_owned()));
+    assert!(manifest.supports(&garlic_version()));
+    assert_eq!(
+        Manifest::parse(&manifest.to_file_string()).unwrap(),
+        manifest
+    );
+
+    let manifest =
+        Manifest::parse("garlic-version = \"^0.2.1\"\ntemplate-revision = \"abc123\"\n").unwrap();
+    assert!(manifest.supports(&version("0.2.4")));
+    assert!(!manifest.supports(&version("0.3.0")));
+    assert!(!manifest.supports(&version("0.2.0")));
+    assert_eq!(manifest.template_revision.as_deref(), Some("abc123"));
+
+    assert!(Manifest::default().supports(&version("9.0.0")));
+    assert!(Manifest::parse("garlic-version = \"newest\"\n").is_err());
+}
+
+#[test]
+fn test_complete_commands() {
+    let candidates = |line: &str| {
+        let args: Vec<_> = line.split(' ').map(Into::into).collect();
+        let index = args.len() - 1;
+        complete(&mut GarlicParser::command(), args, index, None)
+            .unwrap()
+            .into_iter()
+            .map(|candidate| candidate.get_value().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+
+    let db = candidates("garlic db:");
+    assert!(db.contains(&"db:seed".to_owned()));
+    assert!(db.contains(&"db:snapshot".to_owned()));
+    assert!(!db.contains(&"migrate:new".to_owned()));
+
+    assert!(candidates("garlic se").contains(&"seed".to_owned()));
+    assert!(candidates("garlic completions ").contains(&"powershell".to_owned()));
+
+    for shell in Shell::value_variants() {
+        assert!(Shells::builtins().completer(&shell.to_string()).is_some());
+    }
+
+    let bash = completion_script(Shell::Bash);
+    assert!(bash.contains("-F _garlic garlic"));
+    assert!(!bash.contains("COMP_WORDBREAKS="));
+}
+
+#[test]
+fn test_docs_cover_every_command() {
+    let markdown = render_docs(HelpFormat::Markdown);
+    let man = render_docs(HelpFormat::Man);
+    assert!(!markdown.contains("<WRAPPER>") && !man.contains("<WRAPPER>"));
+
+    let (database, wrappers) = markdown
+        .split_once("## Database")
+        .and_then(|(_, rest)| rest.split_once("## Wrappers"))
+        .expect("Expected a database and a wrapper section");
+    assert!(database.contains("### `db:seed`"));
+    assert!(database.contains("Aliases: `seed`"));
+    assert!(wrappers.contains("### `run:frontend`"));
+    assert!(!database.contains("### `run:frontend`"));
+    assert!(man.contains(".SH BUILD & RELEASE"));
+
+    for command in GarlicCommand::iter() {
+        assert!(markdown.contains(&format!("### `{command}`")));
+        assert!(man.contains(&format!(
+            "garlic {}",
+            command.to_string().replace('-', "\\-")
+        )));
+    }
+}
+
+#[test]
+fn test_wrap_help_text() {
+    assert_eq!(
+        wrap("Runs the SQL files in seeds/ that haven't run yet", 20),
+        ["Runs the SQL files", "in seeds/ that", "haven't run yet"]
+    );
+    assert_eq!(wrap("a-very-long-word b", 4), ["a-very-long-word", "b"]);
+    assert_eq!(wrap("first\nsecond", 80), ["first", "second"]);
+}
+
+#[test]
+fn test_every_command_has_a_category() {
+    let root_cmd = GarlicParser::command();
+    for command in GarlicCommand::iter() {
+        let category = command.category();
+        assert!(category.is_some(), "Expected {command} to have a category");
+
+        let about = root_cmd
+            .find_subcommand(command.to_string())
+            .and_then(|subcommand| subcommand.get_about())
+            .map(|about| about.to_string())
+            .unwrap_or_default();
+        assert_eq!(
+            category == Some(Category::Wrappers),
+            about.starts_with("Wrapper for"),
+            "{command}"
+        );
+    }
+}
+
+#[test]
+fn test_suggest_mistyped_commands() {
+    assert_eq!(edit_distance("run:fronted", "run:frontend"), 1);
+    assert_eq!(edit_distance("sevrer", "server"), 1);
+    assert_eq!(edit_distance("", "dev"), 3);
+
+    let names = |typed: &str| -> Vec<String> {
+        suggest_commands(typed)
+            .into_iter()
+            .map(|suggestion| suggestion.name)
+            .collect()
+    };
+
+    assert_eq!(names("run:fronted")[0], "run:frontend");
+    assert_eq!(
+        suggest_commands("sevrer")[0],
+        Suggestion {
+            name: "server".to_owned(),
+            alias_of: Some("run:backend".to_owned())
+        }
+    );
+    assert_eq!(names("frontnd")[0], "frontend");
+    assert_eq!(
+        names("migrate:run"),
+        ["migrate:up", "migrate:down", "migrate:new"]
+    );
+    assert_eq!(
+        names("db:wipe"),
+        [
+            "db:snapshot",
+            "db:restore",
+            "db:seed",
+            "db:snapshots",
+            "db:snapshot:delete"
+        ]
+    );
+    assert!(names("xyzzy").is_empty());
+}
+
+#[test]
+fn test_pass_through_wrapper_args() {
+    let project = Project::at("/tmp/garlic-wrappers");
+    let runs_with = |package_manager: PackageManager, line: &str| {
+        let garlic = GarlicParser::try_parse_from(split(line).unwrap()).unwrap();
+        garlic
+            .command
+            .unwrap()
+            .wrapped_cmd(&project.clone().with_package_manager(Some(package_manager)))
+            .unwrap()
+            .command_line()
+            .to_owned()
+    };
+    let runs = |line: &str| runs_with(PackageManager::Bun, line);
+
+    assert_eq!(
+        runs("garlic server -- --release --bin 'a b'"),
+        "cargo run --release --bin 'a b'"
+    );
+    assert_eq!(
+        runs("garlic server --args --release"),
+        "cargo run --release"
+    );
+    assert_eq!(
+        runs("garlic crate serde server -- -F derive"),
+        "cargo add serde --package server -F derive"
+    );
+    assert_eq!(
+        runs("garlic dev -- --port 3000"),
+        "bun x vite dev --port 3000"
+    );
+    assert_eq!(
+        runs_with(PackageManager::Pnpm, "garlic dev -- --port 3000"),
+        "pnpm exec vite dev --port 3000"
+    );
+    assert_eq!(
+        runs_with(PackageManager::Yarn, "garlic preview"),
+        "yarn vite preview"
+    );
+}
+
+#[test]
+fn test_update_versions() {
+    let version = |version: &str| semver::Version::parse(version).unwrap();
+
+    assert_eq!(index_path("garlic-cli"), "ga/rl/garlic-cli");
+    assert_eq!(index_path("syn"), "3/s/syn");
+    assert_eq!(index_path("cc"), "2/cc");
+
+    let index = parse_index(concat!(
+        r#"{"name":"garlic-cli","vers":"0.2.1","yanked":false}"#,
+        "\n",
+        r#"{"name":"garlic-cli","vers":"0.3.0","yanked":true}"#,
+        "\n",
+        r#"{"name":"garlic-cli","vers":"0.2.4","yanked":false}"#,
+        "\n",
+        r#"{"name":"garlic-cli","vers":"0.4.0-beta.1","yanked":false}"#,
+    ));
+    assert_eq!(index.len(), 3);
+    assert_eq!(
+        select_release(&index, None).unwrap().version,
+        version("0.2.4")
+    );
+    assert_eq!(
+        select_release(&index, Some(&version("0.4.0-beta.1")))
+            .unwrap()
+            .tag,
+        "0.4.0-beta.1"
+    );
+    assert_eq!(select_release(&index, Some(&version("0.3.0"))), None);
+
+    let tags = parse_tags("abc\trefs/tags/v0.3.0\ndef\trefs/tags/0.2.0\n123\trefs/tags/nightly");
+    assert_eq!(tags.len(), 2);
+    assert_eq!(select_release(&tags, None).unwrap().tag, "v0.3.0");
+
+    assert_eq!(
+        "git:https://example.com/garlic.git".parse(),
+        Ok(UpdateSource::Git(
+            "https://example.com/garlic.git".to_owned()
+        ))
+    );
+    assert_eq!(
+        "index:sparse+https://mirror.example.com/".parse(),
+        Ok(UpdateSource::Index(
+            "https://mirror.example.com/".to_owned()
+        ))
+    );
+    assert!("crates".parse::<UpdateSource>().is_err());
+
+    let changelog = "# Changelog\n\n## [Unreleased]\n\n- Next\n\n## [0.3.0] - 2026-10-01\n\n- Three\n\n## 0.2.4\n\n- Two four\n\n## v0.2.1\n\n- Two one\n";
+    assert_eq!(
+        changelog_range(changelog, &version("0.2.1"), &version("0.3.0")),
+        "## [0.3.0] - 2026-10-01\n\n- Three\n\n## 0.2.4\n\n- Two four"
+    );
+    assert_eq!(
+        changelog_range(changelog, &version("0.3.0"), &version("0.4.0")),
+        ""
+    );
+}
+
+#[test]
+fn test_upgrade_plan() {
+    let [base, new, local] = [b"a\nb\n".as_slice(), b"a\nB\n", b"A\nb\n"];
+
+    assert_eq!(plan(Some(base), Some(new), Some(base)), Action::Write);
+    assert_eq!(plan(None, Some(new), None), Action::Write);
+    assert_eq!(plan(Some(base), None, Some(base)), Action::Delete);
+    assert_eq!(plan(Some(base), Some(new), Some(new)), Action::Keep);
+    assert_eq!(plan(Some(base), None, None), Action::Keep);
+    assert_eq!(plan(Some(base), Some(new), Some(local)), Action::Merge);
+    assert_eq!(plan(Some(base), Some(new), None), Action::DeletedLocally);
+    assert_eq!(plan(Some(base), None, Some(local)), Action::Reject);
+    assert_eq!(plan(None, Some(new), Some(local)), Action::Reject);
+    assert_eq!(
+        plan(Some(b"\0a"), Some(b"\0b"), Some(b"\0c")),
+        Action::Reject
+    );
+
+    assert_eq!(
+        changed_files("app/package.json\n.garlic\n.garlic/manifest.toml\nsrc/main.rs\n"),
+        [
+            PathBuf::from("app/package.json"),
+            PathBuf::from("src/main.rs")
+        ]
+    );
+}
+
+#[test]
+fn test_info_masks_env() {
+    assert_eq!(mask("hunter2"), "********");
+    assert_eq!(mask(""), "");
+
+    let root = TempDir::new("garlic-info").unwrap();
+    let env_file = root.path().join(".env");
+    fs::write(&env_file, "JWT_SECRET=hunter2\nOPENAPI_TITLE=\n").unwrap();
+
+    let env = masked_env(&DotEnvs::load(&env_file).unwrap());
+    assert_eq!(env.len(), 2);
+    assert_eq!(env["JWT_SECRET"], "********");
+    assert_eq!(env["OPENAPI_TITLE"], "");
+}
@@ -0,0 +1,302 @@
+use crate::{dry_run, dry_run_print, error, garlic_print, warning, Cmd, Manifest, Project};
+use colored::Colorize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tempdir::TempDir;
+
+/// What `garlic upgrade` does with a file that changed in the template
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// The project already has the new version
+    Keep,
+    /// The project didn't change the file, so it's replaced by the new version
+    Write,
+    /// The project didn't change the file and the template removed it
+    Delete,
+    /// Both changed the file, their changes are merged line by line
+    Merge,
+    /// Both changed the file in ways that can't be merged, the template's diff goes into a `.rej` file
+    Reject,
+    /// The template changed a file the project deleted, it stays deleted
+    DeletedLocally,
+}
+
+/// Decides what to do with a file from its contents at the recorded template revision, the new
+/// revision and in the project. `None` means the file doesn't exist there.
+pub fn plan(base: Option<&[u8]>, new: Option<&[u8]>, local: Option<&[u8]>) -> Action {
+    let binary = |content: Option<&[u8]>| content.is_some_and(|content| content.contains(&0));
+
+    if local == new {
+        Action::Keep
+    } else if local == base {
+        match new {
+            Some(_) => Action::Write,
+            None => Action::Delete,
+        }
+    } else if base.is_some() && new.is_some() && local.is_none() {
+        Action::DeletedLocally
+    } else if [base, new, local].iter().any(Option::is_none)
+        || [base, new, local].into_iter().any(binary)
+    {
+        Action::Reject
+    } else {
+        Action::Merge
+    }
+}
+
+/// The files that changed between two revisions in the output of `git diff --name-only`,
+/// without the ones garlic manages itself
+pub fn changed_files(output: &str) -> Vec<PathBuf> {
+    output
+        .lines()
+        .filter(|path| !path.is_empty())
+        .filter(|path| {
+            *path != ".garlic" && !path.starts_with(".garlic/") && !path.starts_with(".garlic.d/")
+        })
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// `garlic upgrade`: applies the template's changes since the project's recorded revision.
+/// Files the project didn't touch are updated, other changes are merged with conflict markers
+/// or, when that isn't possible, written to `<FILE>.rej`.
+pub fn upgrade(project: &Project, template: &str, from: Option<&str>, to: Option<&str>) {
+    let mut manifest = Manifest::load(&project.root);
+    let base = from
+        .or(manifest.template_revision.as_deref())
+        .unwrap_or_else(|| {
+            error(
+                "no_template_revision",
+                "The manifest doesn't record which template revision the project was made from. \
+                Pass it with --from <REV>",
+            )
+        })
+        .to_owned();
+
+    let tempdir =
+        TempDir::new("garlic-upgrade").expect("Expected to be able to create temporary directory");
+    let new_dir = tempdir.path().join("new");
+    let base_dir = tempdir.path().join("base");
+
+    garlic_print(format!("Fetching the template from {}", template.cyan()));
+    git(tempdir.path(), &["clone", "--quiet", template, "new"]);
+    let base = revision(&new_dir, &base);
+    let target = revision(&new_dir, to.unwrap_or("HEAD"));
+
+    if base == target {
+        garlic_print(format!(
+            "The project is up to date with template {}",
+            short(&target)
+        ));
+        return;
+    }
+
+    git(&new_dir, &["checkout", "--quiet", "--detach", &target]);
+    let base_path = base_dir.to_string_lossy();
+    git(
+        &new_dir,
+        &["worktree", "add", "--quiet", "--detach", &base_path, &base],
+    );
+
+    let files = changed_files(&git(
+        &new_dir,
+        &["diff", "--name-only", "--no-renames", &base, &target],
+    ));
+    garlic_print(format!(
+        "Upgrading from template {} to {}",
+        short(&base),
+        short(&target)
+    ));
+
+    let mut conflicts = Vec::new();
+    let mut deleted_locally = Vec::new();
+    for file in &files {
+        let base_file = base_dir.join(file);
+        let new_file = new_dir.join(file);
+        let local_file = project.root.join(file);
+        let [base_content, new_content, local_content] =
+            [&base_file, &new_file, &local_file].map(|path| fs::read(path).ok());
+
+        let action = plan(
+            base_content.as_deref(),
+            new_content.as_deref(),
+            local_content.as_deref(),
+        );
+        let status = match action {
+            Action::Keep => continue,
+            Action::Write if local_content.is_none() => {
+                write(&local_file, new_content.unwrap_or_default());
+                "added".green()
+            }
+            Action::Write => {
+                write(&local_file, new_content.unwrap_or_default());
+                "updated".green()
+            }
+            Action::Delete => {
+                remove(&local_file);
+                "deleted".green()
+            }
+            Action::Merge => {
+                let (merged, clean) = merge(&local_file, &base_file, &new_file, &base, &target);
+                write(&local_file, merged);
+                match clean {
+                    true => "merged".green(),
+                    false => {
+                        conflicts.push(file.clone());
+                        "conflict".red()
+                    }
+                }
+            }
+            Action::Reject => {
+                let diff = git(
+                    &new_dir,
+                    &["diff", &base, &target, "--", &file.to_string_lossy()],
+                );
+                write(&rej_path(&local_file), diff);
+                conflicts.push(file.clone());
+                "rejected".red()
+            }
+            Action::DeletedLocally => {
+                deleted_locally.push(file.clone());
+                "deleted locally".yellow()
+            }
+        };
+
+        garlic_print(format!("{status} {}", file.display()));
+    }
+
+    if !deleted_locally.is_empty() {
+        garlic_print(format!(
+            "The template changed files the project deleted, they stay deleted: {}",
+            join(&deleted_locally)
+        ));
+    }
+
+    manifest.template_revision = Some(target.clone());
+    manifest.save(&project.root);
+
+    match conflicts.is_empty() {
+        true => garlic_print(format!("Upgraded to template {}", short(&target))),
+        false => warning(
+            "upgrade_conflicts",
+            format!(
+                "Upgraded to template {}, resolve the conflict markers or .rej files of: {}",
+                short(&target),
+                join(&conflicts)
+            ),
+        ),
+    }
+}
+
+/// Merges the template's changes into `local`, true when there were no conflicts
+fn merge(local: &Path, base: &Path, new: &Path, base_rev: &str, new_rev: &str) -> (String, bool) {
+    let output = Cmd::new("git", ["merge-file", "-p", "-L", "project", "-L"])
+        .arg(short(base_rev))
+        .arg("-L")
+        .arg(short(new_rev))
+        .args([local, base, new])
+        .read_only()
+        .capture()
+        .unwrap_or_else(|e| error("git_failed", format!("Couldn't run git merge-file: {e}")));
+
+    // The exit code is the number of conflicts, or negative on errors
+    match output.code {
+        Some(0) => (output.stdout, true),
+        Some(1..=127) => (output.stdout, false),
+        _ => error(
+            "git_failed",
+            format!(
+                "Couldn't merge {}: {}",
+                local.display(),
+                output.stderr.trim()
+            ),
+        ),
+    }
+}
+
+/// Runs git in `dir` to look something up in the template, also with `--dry-run`
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Cmd::new("git", args)
+        .dir(dir)
+        .read_only()
+        .capture()
+        .unwrap_or_else(|e| error("git_failed", format!("Expected git to be installed: {e}")));
+
+    if !output.success() {
+        error(
+            "git_failed",
+            format!("git {} failed: {}", args.join(" "), output.stderr.trim()),
+        );
+    }
+
+    output.stdout
+}
+
+fn revision(repo: &Path, name: &str) -> String {
+    let output = Cmd::new("git", ["rev-parse", "--verify", "--quiet"])
+        .arg(format!("{name}^{{commit}}"))
+        .dir(repo)
+        .read_only()
+        .capture()
+        .unwrap_or_else(|e| error("git_failed", format!("Expected git to be installed: {e}")));
+
+    if !output.success() {
+        error(
+            "unknown_revision",
+            format!("Expected the template to have a revision {name}"),
+        );
+    }
+
+    output.stdout.trim().to_owned()
+}
+
+fn join(files: &[PathBuf]) -> String {
+    files
+        .iter()
+        .map(|file| file.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn short(revision: &str) -> &str {
+    &revision[..revision.len().min(10)]
+}
+
+fn rej_path(path: &Path) -> PathBuf {
+    let mut rej = path.as_os_str().to_owned();
+    rej.push(".rej");
+    PathBuf::from(rej)
+}
+
+fn write(path: &Path, content: impl AsRef<[u8]>) {
+    if dry_run() {
+        dry_run_print(format!("Would write {}", path.display()));
+        return;
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).expect("Expected to be able to create directory");
+    }
+    fs::write(path, content).unwrap_or_else(|e| {
+        error(
+            "write_failed",
+            format!("Couldn't write {}: {e}", path.display()),
+        )
+    });
+}
+
+fn remove(path: &Path) {
+    if dry_run() {
+        dry_run_print(format!("Would delete {}", path.display()));
+        return;
+    }
+
+    fs::remove_file(path).unwrap_or_else(|e| {
+        error(
+            "write_failed",
+            format!("Couldn't delete {}: {e}", path.display()),
+        )
+    });
+}